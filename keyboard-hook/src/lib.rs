@@ -1,9 +1,16 @@
+#![cfg(windows)]
+
 use std::sync::atomic::{AtomicPtr, Ordering};
 use winapi::shared::minwindef::{DWORD, LPARAM, LRESULT, WPARAM};
-use winapi::shared::windef::HHOOK;
+use winapi::shared::windef::{HHOOK__, HWND};
 use winapi::um::winuser;
 
+/// 鼠标钩子的回调: 消息、mouseData、标志，返回true时屏蔽这个鼠标事件
+pub type MouseCallback = extern "system" fn(u32, DWORD, DWORD) -> bool;
+
 static HOOK_HANDLE: AtomicPtr<HHOOK__> = AtomicPtr::new(std::ptr::null_mut());
+static MOUSE_HOOK_HANDLE: AtomicPtr<HHOOK__> = AtomicPtr::new(std::ptr::null_mut());
+static MOUSE_CALLBACK: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
 static CALLBACK_WINDOW: AtomicPtr<i32> = AtomicPtr::new(std::ptr::null_mut());
 
 #[no_mangle]
@@ -40,11 +47,49 @@ pub extern "system" fn uninstall_hook() -> bool {
     }
 }
 
+/// 安装低级鼠标钩子，按键和滚轮消息交给callback决定是否屏蔽。
+/// 回调在安装钩子的线程上执行，这个线程需要持续处理消息循环
+#[no_mangle]
+pub extern "system" fn install_mouse_hook(callback: MouseCallback) -> bool {
+    unsafe {
+        MOUSE_CALLBACK.store(callback as *mut (), Ordering::SeqCst);
+        
+        let hook = winuser::SetWindowsHookExA(
+            winuser::WH_MOUSE_LL,
+            Some(mouse_hook_proc),
+            std::ptr::null_mut(),
+            0,
+        );
+        
+        if hook.is_null() {
+            MOUSE_CALLBACK.store(std::ptr::null_mut(), Ordering::SeqCst);
+            return false;
+        }
+        
+        MOUSE_HOOK_HANDLE.store(hook, Ordering::SeqCst);
+        true
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn uninstall_mouse_hook() -> bool {
+    unsafe {
+        MOUSE_CALLBACK.store(std::ptr::null_mut(), Ordering::SeqCst);
+        
+        let hook = MOUSE_HOOK_HANDLE.load(Ordering::SeqCst);
+        if !hook.is_null() {
+            let result = winuser::UnhookWindowsHookEx(hook);
+            MOUSE_HOOK_HANDLE.store(std::ptr::null_mut(), Ordering::SeqCst);
+            return result != 0;
+        }
+        false
+    }
+}
+
 unsafe extern "system" fn keyboard_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
     if code >= 0 {
         let window = CALLBACK_WINDOW.load(Ordering::SeqCst);
         if !window.is_null() {
-            let key_info = *(lparam as *const winuser::KBDLLHOOKSTRUCT);
             winuser::PostMessageA(
                 window as HWND,
                 winuser::WM_USER + 1, // 自定义消息
@@ -54,4 +99,19 @@ unsafe extern "system" fn keyboard_hook_proc(code: i32, wparam: WPARAM, lparam:
         }
     }
     winuser::CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
-} 
\ No newline at end of file
+}
+
+unsafe extern "system" fn mouse_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    // 鼠标移动太频繁，只转发按键和滚轮消息
+    if code >= 0 && wparam as u32 != winuser::WM_MOUSEMOVE {
+        let callback = MOUSE_CALLBACK.load(Ordering::SeqCst);
+        if !callback.is_null() {
+            let callback: MouseCallback = std::mem::transmute(callback);
+            let mouse_info = *(lparam as *const winuser::MSLLHOOKSTRUCT);
+            if callback(wparam as u32, mouse_info.mouseData, mouse_info.flags) {
+                return 1;
+            }
+        }
+    }
+    winuser::CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
+}
\ No newline at end of file
@@ -1,20 +1,25 @@
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
-use log::{info, error};
+use log::info;
 use std::io::{self, Write};
 use std::thread;
-use winapi::um::winuser::{GetMessageA, TranslateMessage, DispatchMessageA, MSG};
-use winapi::shared::minwindef::BOOL;
+#[cfg(windows)]
+use winapi::um::winuser::{GetMessageA, TranslateMessage, DispatchMessageA, PostThreadMessageA, MSG, WM_QUIT};
+#[cfg(windows)]
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+#[cfg(not(windows))]
+use std::time::Duration;
 
-use crate::keyboard::{KeyboardListener, KeyboardSimulator};
+use crate::keyboard::KeyboardListener;
 use crate::config::ConfigManager;
 use crate::macro_engine::MacroEngine;
 use crate::heroes::HeroRegistry;
 
 pub struct App {
+    // 运行时只需要和应用活得一样久
+    #[allow(dead_code)]
     runtime: Runtime,
     keyboard_listener: Arc<KeyboardListener>,
-    keyboard_simulator: Arc<KeyboardSimulator>,
     config_manager: Arc<Mutex<ConfigManager>>,
     macro_engine: Arc<MacroEngine>,
     hero_registry: Arc<Mutex<HeroRegistry>>,
@@ -32,20 +37,21 @@ impl App {
         // 初始化配置管理器
         let config_manager = Arc::new(Mutex::new(ConfigManager::new()?));
         
-        // 初始化键盘监听和模拟器
-        let keyboard_simulator = Arc::new(KeyboardSimulator::new());
+        // 当前选中的英雄
         let active_hero = Arc::new(Mutex::new(String::from("default")));
         
         // 初始化英雄注册表
         let hero_registry = Arc::new(Mutex::new(HeroRegistry::new()));
         
+        // 读取全局设置
+        let settings = config_manager.lock().unwrap().load_settings()?;
+        
         // 初始化宏引擎
         let macro_engine = Arc::new(MacroEngine::new(
-            keyboard_simulator.clone(),
             hero_registry.clone(),
             active_hero.clone(),
+            settings,
         ));
-        
         // 初始化键盘监听器
         let keyboard_listener = Arc::new(KeyboardListener::new(macro_engine.clone())?);
         
@@ -55,7 +61,6 @@ impl App {
         Ok(Self {
             runtime,
             keyboard_listener,
-            keyboard_simulator,
             config_manager,
             macro_engine,
             hero_registry,
@@ -65,8 +70,26 @@ impl App {
     }
     
     pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_heroes()?;
+        MacroEngine::start_timer(&self.macro_engine);
+        
+        // 命令行在单独的线程里读取输入，退出时结束消息循环
+        let active_hero = self.active_hero.clone();
+        let hero_registry = self.hero_registry.clone();
+        let running = self.running.clone();
+        let quit = Self::message_loop_quitter();
+        thread::spawn(move || {
+            Self::command_line_interface(active_hero, hero_registry, running);
+            quit();
+        });
+        
+        // 钩子回调在安装钩子的线程上执行，监听器和消息循环都在当前线程
+        self.keyboard_listener.start()?;
+        
         info!("启动消息循环...");
-        Self::run_message_loop();
+        self.run_message_loop();
+        
+        self.keyboard_listener.stop()?;
         Ok(())
     }
     
@@ -170,7 +193,8 @@ impl App {
         // 注册英雄到注册表
         let mut registry = self.hero_registry.lock().unwrap();
         
-        // 注册预定义英雄
+        // 注册预定义英雄，default是启动时的空白配置
+        registry.register_hero("default", crate::heroes::template::create_empty_config("default"));
         registry.register_hero("yasuo", crate::heroes::yasuo::create_config());
         registry.register_hero("riven", crate::heroes::riven::create_config());
         
@@ -184,7 +208,17 @@ impl App {
         Ok(())
     }
 
-    fn run_message_loop() {
+    /// 返回从其他线程结束消息循环的方法
+    #[cfg(windows)]
+    fn message_loop_quitter() -> impl FnOnce() + Send {
+        let thread_id = unsafe { GetCurrentThreadId() };
+        move || unsafe {
+            PostThreadMessageA(thread_id, WM_QUIT, 0, 0);
+        }
+    }
+    
+    #[cfg(windows)]
+    fn run_message_loop(&self) {
         unsafe {
             let mut msg: MSG = std::mem::zeroed();
             while GetMessageA(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {
@@ -193,4 +227,18 @@ impl App {
             }
         }
     }
+    
+    /// 没有消息循环的平台上命令行退出时设置running，不需要额外通知
+    #[cfg(not(windows))]
+    fn message_loop_quitter() -> impl FnOnce() + Send {
+        || {}
+    }
+    
+    /// 轮询线程在后台运行，当前线程等待到命令行退出
+    #[cfg(not(windows))]
+    fn run_message_loop(&self) {
+        while *self.running.lock().unwrap() {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
 } 
\ No newline at end of file
@@ -7,20 +7,129 @@ pub struct ComboAction {
     pub keys: Vec<KeyAction>,
     /// 是否阻止原始按键继续传递
     pub block_original: bool,
+    /// 触发时已有连招在执行的处理方式
+    #[serde(default)]
+    pub on_busy: BusyPolicy,
+    /// 覆盖英雄设置的Press按住时间 (毫秒)
+    #[serde(default)]
+    pub press_duration: Option<u64>,
+    /// 覆盖英雄设置的动作间隔 (毫秒)
+    #[serde(default)]
+    pub action_gap: Option<u64>,
+}
+
+impl ComboAction {
+    /// 用此连招的覆盖值替换英雄的默认时间参数
+    pub fn timing(&self, defaults: ActionTiming) -> ActionTiming {
+        ActionTiming {
+            press_duration: self.press_duration.unwrap_or(defaults.press_duration),
+            action_gap: self.action_gap.unwrap_or(defaults.action_gap),
+        }
+    }
+}
+
+/// 模拟按键的时间参数
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ActionTiming {
+    /// Press动作从按下到松开的时间 (毫秒)
+    #[serde(default = "default_press_duration")]
+    pub press_duration: u64,
+    /// 相邻两个动作之间额外等待的时间 (毫秒)
+    #[serde(default)]
+    pub action_gap: u64,
+}
+
+fn default_press_duration() -> u64 {
+    50
+}
+
+impl Default for ActionTiming {
+    fn default() -> Self {
+        Self {
+            press_duration: default_press_duration(),
+            action_gap: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum BusyPolicy {
+    /// 排在正在执行的连招之后
+    #[default]
+    Queue,
+    /// 直接丢弃这次触发
+    Drop,
+    /// 取消正在执行和排队的连招，立即执行这一个
+    Preempt,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum KeyAction {
-    /// 按下并松开一个键
+    /// 按下一个键，按住press_duration毫秒后松开
     Press(Key),
     /// 按下一个键
     Down(Key),
     /// 松开一个键
     Up(Key),
+    /// 按住一个键指定毫秒后松开
+    Hold(Key, u64),
     /// 等待指定毫秒
     Delay(u64),
-    /// 鼠标点击(坐标偏移, 按钮)
+    /// 在相对当前光标偏移(x, y)的位置点击，点击后光标回到原位；偏移按系统缩放比例换算
     MouseClick(i32, i32, MouseButton),
+    /// 在指定位置点击，点击后光标回到原位
+    MouseClickAt(MousePosition, MouseButton),
+    /// 光标相对当前位置移动(x, y)，不回到原位；偏移按系统缩放比例换算
+    MouseMoveBy(i32, i32),
+    /// 光标移动到指定位置，不回到原位
+    MouseMoveTo(MousePosition),
+    /// 在当前位置按下鼠标按键
+    MouseDown(MouseButton),
+    /// 在当前位置松开鼠标按键
+    MouseUp(MouseButton),
+    /// 在当前位置按下按键，相对移动(x, y)后松开，光标停在拖动的终点
+    MouseDrag(i32, i32, MouseButton),
+    /// 滚轮滚动若干格，正数向下、负数向上，光标不动
+    Scroll(i32),
+    /// 逐字输入一段文字，支持中文等任意Unicode字符。
+    /// 花括号里的按键名按对应的键发送，如{Enter}、{Tab}；{{和}}输入花括号本身
+    TypeText {
+        text: String,
+        /// 输入前按Enter打开聊天框
+        #[serde(default)]
+        open_chat: bool,
+        /// 输入后按Enter发送
+        #[serde(default)]
+        send: bool,
+        /// 每个字符之前等待的时间 (毫秒)
+        #[serde(default = "default_char_delay")]
+        char_delay: u64,
+    },
+    /// 把body重复执行count次
+    Repeat { count: u32, body: Vec<KeyAction> },
+    /// key按住期间反复执行body，开始时没有按住则一次也不执行
+    WhileHeld { key: Key, body: Vec<KeyAction> },
+    /// 执行另一个连招的动作，连招之间不能递归调用
+    Call(String),
+}
+
+fn default_char_delay() -> u64 {
+    10
+}
+
+impl KeyAction {
+    /// 收集此动作及其嵌套动作直接调用的连招名
+    fn collect_calls<'a>(&'a self, calls: &mut Vec<&'a str>) {
+        match self {
+            KeyAction::Call(name) => calls.push(name),
+            KeyAction::Repeat { body, .. } | KeyAction::WhileHeld { body, .. } => {
+                for action in body {
+                    action.collect_calls(calls);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -28,26 +137,340 @@ pub enum Key {
     Character(char),
     // 各种特殊按键...
     Escape, Tab, CapsLock, Shift, Control, Alt, Space, Enter,
+    // 区分左右的修饰键，Shift、Control、Alt表示任意一侧
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    // 鼠标按键和滚轮，滚轮每滚动一格算一次按下并松开
+    Mouse(MouseButton), WheelUp, WheelDown,
     // 数字键和功能键...
     F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
     // 其他按键...
 }
 
+impl Key {
+    /// 区分左右的修饰键
+    pub const MODIFIER_SIDES: [Key; 6] = [
+        Key::LShift, Key::RShift, Key::LControl, Key::RControl, Key::LAlt, Key::RAlt,
+    ];
+    
+    /// 左右两侧的修饰键对应的通用修饰键
+    pub fn generic_modifier(&self) -> Option<Key> {
+        match self {
+            Key::LShift | Key::RShift => Some(Key::Shift),
+            Key::LControl | Key::RControl => Some(Key::Control),
+            Key::LAlt | Key::RAlt => Some(Key::Alt),
+            _ => None,
+        }
+    }
+    
+    /// 滚轮没有按住的状态，按下之后立即松开
+    pub fn is_wheel(&self) -> bool {
+        matches!(self, Key::WheelUp | Key::WheelDown)
+    }
+    
+    /// 通用修饰键对应的左右两侧
+    pub fn modifier_sides(&self) -> Option<[Key; 2]> {
+        match self {
+            Key::Shift => Some([Key::LShift, Key::RShift]),
+            Key::Control => Some([Key::LControl, Key::RControl]),
+            Key::Alt => Some([Key::LAlt, Key::RAlt]),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum MouseButton {
     Left,
     Right,
     Middle,
+    /// 侧键 (后退)
+    X1,
+    /// 侧键 (前进)
+    X2,
+}
+
+/// 鼠标动作的目标位置，执行时按屏幕和游戏窗口的实际大小换算成屏幕坐标
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum MousePosition {
+    /// 屏幕像素坐标，不随分辨率和缩放变化
+    Pixels { x: i32, y: i32 },
+    /// 占整个屏幕宽高的比例，(0.5, 0.5)是屏幕中心
+    Screen { x: f64, y: f64 },
+    /// 占游戏窗口宽高的比例，找不到游戏窗口时按整个屏幕计算
+    Window { x: f64, y: f64 },
+    /// 相对当前光标的偏移，单位是按系统缩放比例换算后的像素
+    Cursor { dx: i32, dy: i32 },
+    /// 相对锚点的偏移，单位是游戏窗口高度的比例
+    Anchor {
+        anchor: ScreenAnchor,
+        #[serde(default)]
+        dx: f64,
+        #[serde(default)]
+        dy: f64,
+    },
+}
+
+/// 鼠标位置可以引用的命名锚点
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ScreenAnchor {
+    /// 屏幕中心
+    ScreenCenter,
+    /// 游戏窗口中心
+    WindowCenter,
+    /// 游戏窗口左上角
+    WindowTopLeft,
+    /// 小地图的外侧角，默认布局下是游戏窗口右下角
+    MinimapCorner,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum SequenceOrder {
+    /// 不要求顺序，只要求所有按键都在时间窗口内出现
+    #[default]
+    Any,
+    /// 严格按照sequence的顺序依次按下
+    Strict {
+        /// 每一步距上一步的最大间隔 (毫秒)，与展开后的按键一一对应，第一项不使用；缺省时不限制
+        #[serde(default)]
+        max_gaps: Vec<Option<u64>>,
+        /// 是否允许步骤之间夹杂无关按键
+        #[serde(default)]
+        allow_other_keys: bool,
+    },
+    /// 和弦: 所有按键在tolerance_ms毫秒内以任意顺序按下，最后一个键按下时都还按住
+    Chord {
+        tolerance_ms: u64,
+        /// 和弦决定之前先扣下已按下的和弦按键，没有完成时再补发
+        #[serde(default)]
+        suppress: bool,
+    },
+}
+
+impl SequenceOrder {
+    /// 严格顺序，不限制单步间隔，不允许夹杂其他按键
+    pub fn strict() -> Self {
+        SequenceOrder::Strict {
+            max_gaps: Vec::new(),
+            allow_other_keys: false,
+        }
+    }
+    
+    /// 第index步距上一步允许的最大间隔
+    pub fn max_gap(&self, index: usize) -> Option<u64> {
+        match self {
+            SequenceOrder::Strict { max_gaps, .. } if index > 0 => {
+                max_gaps.get(index).copied().flatten()
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum TriggerKind {
+    /// 序列最后一个键按下时触发
+    #[default]
+    Press,
+    /// 最后一个键在max_ms毫秒内松开时触发
+    Tap { max_ms: u64 },
+    /// 最后一个键按住超过min_ms毫秒时触发，不等松开
+    LongPress { min_ms: u64 },
+    /// 最后一个键松开时触发
+    Release,
+    /// 同一个键连按taps次时触发: 每次按下后等待window_ms毫秒，没有再按才按次数决定
+    TapDance { taps: u32, window_ms: u64 },
+}
+
+/// 触发时对修饰键的要求，没有提到的修饰键不影响触发
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModifierRule {
+    /// 必须按住的修饰键，通用修饰键按住任意一侧即可
+    #[serde(default)]
+    pub required: Vec<Key>,
+    /// 按住时不触发的修饰键
+    #[serde(default)]
+    pub forbidden: Vec<Key>,
+    /// 不关心的修饰键，用于从通用修饰键的forbidden中排除某一侧
+    #[serde(default)]
+    pub ignored: Vec<Key>,
+}
+
+impl ModifierRule {
+    /// 按当前按住的按键检查是否满足要求
+    pub fn is_satisfied(&self, is_down: &dyn Fn(&Key) -> bool) -> bool {
+        if !self.required.iter().all(is_down) {
+            return false;
+        }
+        
+        Key::MODIFIER_SIDES.iter()
+            .filter(|side| is_down(side))
+            .all(|side| !self.forbids(side))
+    }
+    
+    /// 某一侧的修饰键是否被禁止，单独提到这一侧的设置优先于通用修饰键的设置
+    fn forbids(&self, side: &Key) -> bool {
+        for key in Some(side.clone()).into_iter().chain(side.generic_modifier()) {
+            if self.required.contains(&key) || self.ignored.contains(&key) {
+                return false;
+            }
+            if self.forbidden.contains(&key) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// 触发序列中的一步，配置中直接写按键即表示单个按键
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Step {
+    Key(Key),
+    /// 其中任意一个键
+    AnyOf { any_of: Vec<Key> },
+    /// 同一个键按下times次
+    Repeat { key: Key, times: usize },
+}
+
+impl From<Key> for Step {
+    fn from(key: Key) -> Self {
+        Step::Key(key)
+    }
+}
+
+impl Step {
+    /// 这一步可以匹配的按键
+    pub fn keys(&self) -> &[Key] {
+        match self {
+            Step::Key(key) | Step::Repeat { key, .. } => std::slice::from_ref(key),
+            Step::AnyOf { any_of } => any_of,
+        }
+    }
+    
+    /// 按住的按键是否满足这一步
+    pub fn is_held(&self, is_down: &dyn Fn(&Key) -> bool) -> bool {
+        self.keys().iter().any(is_down)
+    }
+}
+
+/// 连招完成时额外检查的条件
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Condition {
+    /// 这个键正被按住
+    Held(Key),
+    /// 序列的第from步和第to步之间 (从0开始，按实际按下的顺序) 按过这个键
+    PressedBetween { key: Key, from: usize, to: usize },
+    Not(Box<Condition>),
+    /// 所有条件都满足
+    All(Vec<Condition>),
+    /// 任意一个条件满足
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    /// is_down查询按键是否按住，pressed_between查询两步之间是否按过某个键
+    pub fn evaluate(
+        &self,
+        is_down: &dyn Fn(&Key) -> bool,
+        pressed_between: &dyn Fn(&Key, usize, usize) -> bool,
+    ) -> bool {
+        match self {
+            Condition::Held(key) => is_down(key),
+            Condition::PressedBetween { key, from, to } => pressed_between(key, *from, *to),
+            Condition::Not(condition) => !condition.evaluate(is_down, pressed_between),
+            Condition::All(conditions) => conditions.iter().all(|c| c.evaluate(is_down, pressed_between)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.evaluate(is_down, pressed_between)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComboTrigger {
     /// 触发连招的按键序列
-    pub sequence: Vec<Key>,
+    pub sequence: Vec<Step>,
     /// 序列中的按键必须在此时间窗口内完成 (毫秒)
     pub time_window: Option<u64>,
     /// 如果设置，这个连招将在这些键被屏蔽的情况下触发
     pub block_keys: HashSet<Key>,
+    /// 序列的顺序要求
+    #[serde(default)]
+    pub order: SequenceOrder,
+    /// 同一按键同时完成多个同样长度的连招时，优先级高的触发
+    #[serde(default)]
+    pub priority: i32,
+    /// 设置后序列前面的按键先被暂扣，每一步最多等待此时间 (毫秒)；
+    /// 连招完成则只发送连招动作，否则把暂扣的按键补发给游戏
+    #[serde(default)]
+    pub hold_timeout: Option<u64>,
+    /// 触发后是否消耗匹配用过的按键，消耗后这些按键不能再参与匹配
+    #[serde(default = "default_consume")]
+    pub consume: bool,
+    /// 触发后在此时间内 (毫秒) 不会再次触发
+    #[serde(default)]
+    pub cooldown: Option<u64>,
+    /// 按住按键时系统自动重复的按下是否也能触发此连招
+    #[serde(default)]
+    pub allow_repeat: bool,
+    /// 序列最后一个键以什么方式完成时触发
+    #[serde(default)]
+    pub kind: TriggerKind,
+    /// 设置后只在按住对应层的双功能键时生效；不设置时只在没有启用任何层时生效
+    #[serde(default)]
+    pub layer: Option<String>,
+    /// 对修饰键的要求
+    #[serde(default)]
+    pub modifiers: ModifierRule,
+    /// 设置后只有条件满足时才触发
+    #[serde(default)]
+    pub condition: Option<Condition>,
+    /// 夹在序列步骤之间时视为没有按过的按键，例如移动用的右键
+    #[serde(default)]
+    pub ignored_keys: Vec<Key>,
+}
+
+impl ComboTrigger {
+    /// 每一步都有按键按住
+    pub fn is_held(&self, is_down: &dyn Fn(&Key) -> bool) -> bool {
+        self.sequence.iter().all(|step| step.is_held(is_down))
+    }
+}
+
+fn default_consume() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum PrefixPolicy {
+    /// 短连招立即触发，之后长连招仍可以继续完成
+    #[default]
+    Both,
+    /// 短连招立即触发，并放弃所有未完成的匹配
+    Shorter,
+    /// 等到确定长连招无法完成后才触发短连招
+    Longer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HoldRole {
+    /// 按住时相当于按住这个键，通常是修饰键
+    Modifier(Key),
+    /// 按住时启用这一层的连招
+    Layer(String),
+}
+
+/// 点按时发送自身、按住时作为修饰键或切换层的按键
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DualRoleKey {
+    pub key: Key,
+    /// 按住时的作用
+    pub hold: HoldRole,
+    /// 按住超过此时间 (毫秒) 视为按住；在此之前按下其他键同样视为按住
+    #[serde(default = "default_hold_after")]
+    pub hold_after: u64,
+}
+
+fn default_hold_after() -> u64 {
+    200
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +481,25 @@ pub struct HeroConfig {
     pub combos: HashMap<String, (ComboTrigger, ComboAction)>,
     /// 切换到此英雄的快捷键
     pub hotkey: Option<Vec<Key>>,
+    /// 一个连招是另一个连招的前缀时的处理方式
+    #[serde(default)]
+    pub prefix_policy: PrefixPolicy,
+    /// 按下后清空按键序列和所有未完成匹配的按键
+    #[serde(default = "default_reset_keys")]
+    pub reset_keys: Vec<Key>,
+    /// 双功能按键
+    #[serde(default)]
+    pub dual_role_keys: Vec<DualRoleKey>,
+    /// 对所有连招的序列匹配都不可见的按键，照常发给游戏
+    #[serde(default)]
+    pub ignored_keys: Vec<Key>,
+    /// 此英雄所有连招默认的按键时间参数
+    #[serde(default)]
+    pub timing: ActionTiming,
+}
+
+fn default_reset_keys() -> Vec<Key> {
+    vec![Key::Escape]
 }
 
 impl HeroConfig {
@@ -66,6 +508,11 @@ impl HeroConfig {
             name: name.to_string(),
             combos: HashMap::new(),
             hotkey: None,
+            prefix_policy: PrefixPolicy::default(),
+            reset_keys: default_reset_keys(),
+            dual_role_keys: Vec::new(),
+            ignored_keys: Vec::new(),
+            timing: ActionTiming::default(),
         }
     }
     
@@ -77,22 +524,249 @@ impl HeroConfig {
         time_window: Option<u64>,
         block_original: bool,
         block_keys: HashSet<Key>,
-    ) {
+    ) -> &mut ComboTrigger {
         let trigger = ComboTrigger {
-            sequence,
+            sequence: sequence.into_iter().map(Step::from).collect(),
             time_window,
             block_keys,
+            order: SequenceOrder::default(),
+            priority: 0,
+            hold_timeout: None,
+            consume: true,
+            cooldown: None,
+            allow_repeat: false,
+            kind: TriggerKind::default(),
+            layer: None,
+            modifiers: ModifierRule::default(),
+            condition: None,
+            ignored_keys: Vec::new(),
         };
         
         let action = ComboAction {
             keys: actions,
             block_original,
+            on_busy: BusyPolicy::default(),
+            press_duration: None,
+            action_gap: None,
         };
         
         self.combos.insert(name.to_string(), (trigger, action));
+        
+        // 返回触发条件，便于调用方继续设置可选字段
+        &mut self.combos.get_mut(name).unwrap().0
+    }
+    
+    pub fn action_mut(&mut self, name: &str) -> Option<&mut ComboAction> {
+        self.combos.get_mut(name).map(|(_, action)| action)
     }
     
     pub fn set_hotkey(&mut self, keys: Vec<Key>) {
         self.hotkey = Some(keys);
     }
-} 
\ No newline at end of file
+    
+    /// 是否有连招通过Call调用了此连招
+    pub fn is_called(&self, name: &str) -> bool {
+        self.combos.values().any(|(_, action)| {
+            let mut calls = Vec::new();
+            action.keys.iter().for_each(|key_action| key_action.collect_calls(&mut calls));
+            calls.contains(&name)
+        })
+    }
+    
+    /// 检查Call调用的连招都存在，并且连招之间没有直接或间接的递归调用
+    pub fn check_calls(&self) -> Result<(), String> {
+        // 按名称排序，保证报告的错误与HashMap的遍历顺序无关
+        let mut names: Vec<&String> = self.combos.keys().collect();
+        names.sort();
+        
+        for name in names {
+            self.check_calls_from(name, &mut Vec::new())?;
+        }
+        Ok(())
+    }
+    
+    fn check_calls_from<'a>(&'a self, name: &'a str, stack: &mut Vec<&'a str>) -> Result<(), String> {
+        if let Some(start) = stack.iter().position(|called| *called == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name);
+            return Err(format!("连招递归调用: {}", cycle.join(" -> ")));
+        }
+        
+        let mut calls = Vec::new();
+        if let Some((_, action)) = self.combos.get(name) {
+            action.keys.iter().for_each(|key_action| key_action.collect_calls(&mut calls));
+        }
+        
+        stack.push(name);
+        for called in calls {
+            if !self.combos.contains_key(called) {
+                return Err(format!("连招 {} 调用了不存在的连招 {}", name, called));
+            }
+            self.check_calls_from(called, stack)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+    
+    /// 把动作里的Call展开为被调用连招的动作；不存在或递归的调用原样保留，执行时跳过
+    pub fn expand_calls(&self, actions: &[KeyAction]) -> Vec<KeyAction> {
+        self.expand_calls_within(actions, &mut Vec::new())
+    }
+    
+    fn expand_calls_within<'a>(&'a self, actions: &'a [KeyAction], stack: &mut Vec<&'a str>) -> Vec<KeyAction> {
+        let mut expanded = Vec::new();
+        
+        for action in actions {
+            match action {
+                KeyAction::Call(name) if !stack.contains(&name.as_str()) => {
+                    match self.combos.get(name) {
+                        Some((_, called)) => {
+                            stack.push(name);
+                            expanded.extend(self.expand_calls_within(&called.keys, stack));
+                            stack.pop();
+                        }
+                        None => expanded.push(action.clone()),
+                    }
+                }
+                KeyAction::Repeat { count, body } => expanded.push(KeyAction::Repeat {
+                    count: *count,
+                    body: self.expand_calls_within(body, stack),
+                }),
+                KeyAction::WhileHeld { key, body } => expanded.push(KeyAction::WhileHeld {
+                    key: key.clone(),
+                    body: self.expand_calls_within(body, stack),
+                }),
+                _ => expanded.push(action.clone()),
+            }
+        }
+        
+        expanded
+    }
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn round_trip(step: &Step) -> Step {
+        let json = serde_json::to_string(step).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+    
+    #[test]
+    fn key_step_round_trips_as_plain_key() {
+        let step = Step::Key(Key::Character('E'));
+        
+        assert_eq!(serde_json::to_string(&step).unwrap(), r#"{"Character":"E"}"#);
+        assert_eq!(round_trip(&step), step);
+        
+        let mouse = Step::Key(Key::Mouse(MouseButton::Right));
+        assert_eq!(round_trip(&mouse), mouse);
+    }
+    
+    #[test]
+    fn any_of_step_round_trips() {
+        let step = Step::AnyOf { any_of: vec![Key::Character('Q'), Key::Character('W')] };
+        
+        assert_eq!(serde_json::to_string(&step).unwrap(), r#"{"any_of":[{"Character":"Q"},{"Character":"W"}]}"#);
+        assert_eq!(round_trip(&step), step);
+    }
+    
+    #[test]
+    fn repeat_step_round_trips() {
+        let step = Step::Repeat { key: Key::Character('Q'), times: 3 };
+        
+        assert_eq!(serde_json::to_string(&step).unwrap(), r#"{"key":{"Character":"Q"},"times":3}"#);
+        assert_eq!(round_trip(&step), step);
+    }
+    
+    #[test]
+    fn legacy_sequence_of_bare_keys_still_loads() {
+        // 旧配置的序列只有按键，没有任何新增的字段
+        let json = r#"{
+            "sequence": [{"Character": "E"}, {"Character": "R"}],
+            "time_window": 150,
+            "block_keys": []
+        }"#;
+        let trigger: ComboTrigger = serde_json::from_str(json).unwrap();
+        
+        assert_eq!(trigger.sequence, vec![Step::Key(Key::Character('E')), Step::Key(Key::Character('R'))]);
+        assert_eq!(trigger.time_window, Some(150));
+        assert_eq!(trigger.order, SequenceOrder::default());
+        assert!(trigger.consume);
+    }
+    
+    /// 添加一个以Q触发、执行actions的连招
+    fn combo(config: &mut HeroConfig, name: &str, actions: Vec<KeyAction>) {
+        config.add_combo(name, vec![Key::Character('q')], actions, None, true, HashSet::new());
+    }
+    
+    fn call(name: &str) -> KeyAction {
+        KeyAction::Call(name.to_string())
+    }
+    
+    #[test]
+    fn check_calls_rejects_direct_recursion() {
+        let mut config = HeroConfig::new("test");
+        combo(&mut config, "a", vec![call("a")]);
+        
+        assert_eq!(config.check_calls(), Err("连招递归调用: a -> a".to_string()));
+    }
+    
+    #[test]
+    fn check_calls_rejects_recursion_inside_repeat() {
+        let mut config = HeroConfig::new("test");
+        combo(&mut config, "a", vec![KeyAction::Repeat { count: 2, body: vec![call("b")] }]);
+        combo(&mut config, "b", vec![call("a")]);
+        
+        assert_eq!(config.check_calls(), Err("连招递归调用: a -> b -> a".to_string()));
+    }
+    
+    #[test]
+    fn check_calls_rejects_missing_callee() {
+        let mut config = HeroConfig::new("test");
+        combo(&mut config, "a", vec![call("missing")]);
+        
+        assert_eq!(config.check_calls(), Err("连招 a 调用了不存在的连招 missing".to_string()));
+    }
+    
+    #[test]
+    fn check_calls_allows_shared_callee() {
+        // 两个连招调用同一个连招不是递归
+        let mut config = HeroConfig::new("test");
+        combo(&mut config, "a", vec![call("c"), call("b")]);
+        combo(&mut config, "b", vec![call("c")]);
+        combo(&mut config, "c", vec![KeyAction::Press(Key::Character('w'))]);
+        
+        assert_eq!(config.check_calls(), Ok(()));
+    }
+    
+    #[test]
+    fn expand_calls_inlines_nested_calls() {
+        let mut config = HeroConfig::new("test");
+        combo(&mut config, "b", vec![call("c"), KeyAction::Delay(10)]);
+        combo(&mut config, "c", vec![KeyAction::Press(Key::Character('w'))]);
+        
+        let expanded = config.expand_calls(&[
+            KeyAction::Press(Key::Character('e')),
+            KeyAction::Repeat { count: 2, body: vec![call("b")] },
+            call("missing"),
+        ]);
+        let expected = vec![
+            KeyAction::Press(Key::Character('e')),
+            KeyAction::Repeat { count: 2, body: vec![KeyAction::Press(Key::Character('w')), KeyAction::Delay(10)] },
+            call("missing"),
+        ];
+        assert_eq!(format!("{:?}", expanded), format!("{:?}", expected));
+    }
+    
+    #[test]
+    fn expand_calls_keeps_recursive_call() {
+        let mut config = HeroConfig::new("test");
+        combo(&mut config, "a", vec![KeyAction::Delay(10), call("a")]);
+        
+        let expanded = config.expand_calls(&[call("a")]);
+        let expected = vec![KeyAction::Delay(10), call("a")];
+        assert_eq!(format!("{:?}", expanded), format!("{:?}", expected));
+    }
+}
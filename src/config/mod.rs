@@ -1,12 +1,14 @@
 pub mod hero;
+pub mod settings;
 
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use serde_json;
+use crate::error::AppError;
 
 // 直接导出HeroConfig以便app.rs可以引用
 pub use self::hero::HeroConfig;
+pub use self::settings::EngineSettings;
 
 pub struct ConfigManager {
     config_path: String,
@@ -25,6 +27,19 @@ impl ConfigManager {
         })
     }
     
+    /// 读取全局设置，文件不存在时使用默认值
+    pub fn load_settings(&self) -> Result<EngineSettings, Box<dyn std::error::Error>> {
+        let settings_path = format!("{}/settings.json", self.config_path);
+        
+        if !Path::new(&settings_path).exists() {
+            return Ok(EngineSettings::default());
+        }
+        
+        let settings_str = fs::read_to_string(&settings_path)?;
+        let settings: EngineSettings = serde_json::from_str(&settings_str)?;
+        Ok(settings)
+    }
+    
     pub fn load_hero_configs(&self) -> Result<HashMap<String, HeroConfig>, Box<dyn std::error::Error>> {
         let mut configs = HashMap::new();
         let heroes_path = format!("{}/heroes", self.config_path);
@@ -41,6 +56,8 @@ impl ConfigManager {
             if path.is_file() && path.extension().unwrap_or_default() == "json" {
                 let config_str = fs::read_to_string(&path)?;
                 let config: HeroConfig = serde_json::from_str(&config_str)?;
+                config.check_calls()
+                    .map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))?;
                 configs.insert(config.name.clone(), config);
             }
         }
@@ -0,0 +1,61 @@
+use serde::{Serialize, Deserialize};
+use crate::config::hero::Key;
+
+/// 与具体英雄无关的全局设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineSettings {
+    /// 取消正在执行和排队中的连招的快捷键，不设置时不启用
+    #[serde(default)]
+    pub cancel_hotkey: Option<Key>,
+    /// 每秒最多发出的模拟输入数，None表示不限制
+    #[serde(default = "default_max_actions_per_second")]
+    pub max_actions_per_second: Option<u32>,
+    /// 前导键命令，不设置时不启用
+    #[serde(default)]
+    pub leader: Option<LeaderSettings>,
+}
+
+/// 按下前导键后输入的命令序列
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderSettings {
+    pub key: Key,
+    /// 前导键之后每一步最多等待的时间 (毫秒)，超时后放弃输入
+    #[serde(default = "default_leader_timeout")]
+    pub timeout: u64,
+    pub commands: Vec<LeaderCommand>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderCommand {
+    /// 前导键之后依次按下的按键
+    pub sequence: Vec<Key>,
+    pub action: LeaderAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LeaderAction {
+    /// 切换到指定英雄
+    SwitchHero(String),
+    /// 暂停或恢复所有连招
+    TogglePause,
+    /// 取消正在执行和排队中的连招
+    CancelCombos,
+}
+
+fn default_max_actions_per_second() -> Option<u32> {
+    Some(100)
+}
+
+fn default_leader_timeout() -> u64 {
+    1000
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self {
+            cancel_hotkey: None,
+            max_actions_per_second: default_max_actions_per_second(),
+            leader: None,
+        }
+    }
+}
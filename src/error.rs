@@ -1,6 +1,8 @@
 use thiserror::Error;
 use std::io;
 
+// 部分错误类型和AppResult目前没有用到
+#[allow(dead_code)]
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("IO错误: {0}")]
@@ -22,4 +24,5 @@ pub enum AppError {
     Unknown(String),
 }
 
+#[allow(dead_code)]
 pub type AppResult<T> = Result<T, AppError>; 
\ No newline at end of file
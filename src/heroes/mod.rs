@@ -3,20 +3,26 @@ pub mod yasuo;
 pub mod riven;
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use crate::config::hero::HeroConfig;
+use crate::macro_engine::ComboTrie;
 
 pub struct HeroRegistry {
     heroes: HashMap<String, HeroConfig>,
+    /// 注册时编译好的连招前缀树
+    matchers: HashMap<String, Arc<ComboTrie>>,
 }
 
 impl HeroRegistry {
     pub fn new() -> Self {
         Self {
             heroes: HashMap::new(),
+            matchers: HashMap::new(),
         }
     }
     
     pub fn register_hero(&mut self, name: &str, config: HeroConfig) {
+        self.matchers.insert(name.to_string(), Arc::new(ComboTrie::compile(&config)));
         self.heroes.insert(name.to_string(), config);
     }
     
@@ -24,6 +30,10 @@ impl HeroRegistry {
         self.heroes.get(name)
     }
     
+    pub fn get_matcher(&self, name: &str) -> Option<Arc<ComboTrie>> {
+        self.matchers.get(name).cloned()
+    }
+    
     pub fn get_hero_names(&self) -> Vec<String> {
         self.heroes.keys().cloned().collect()
     }
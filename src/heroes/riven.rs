@@ -1,24 +1,41 @@
 use std::collections::HashSet;
-use crate::config::hero::{HeroConfig, Key, KeyAction, MouseButton};
+use crate::config::hero::{BusyPolicy, HeroConfig, Key, KeyAction, MouseButton};
 
 pub fn create_config() -> HeroConfig {
     let mut config = HeroConfig::new("riven");
     
+    // 取消动画的公共动作，没有触发按键，只供其他连招调用
+    config.add_combo(
+        "cancel_animation",
+        vec![],
+        vec![
+            KeyAction::Delay(50),
+            // 移动鼠标取消动画
+            KeyAction::MouseClick(10, 0, MouseButton::Right),
+        ],
+        None,
+        false,
+        HashSet::new(),
+    );
+    
     // 设置Q取消连招
     config.add_combo(
         "q_cancel",
         vec![Key::Character('q')],
         vec![
             KeyAction::Press(Key::Character('q')),
-            KeyAction::Delay(50),
-            // 移动鼠标取消动画
-            KeyAction::MouseClick(10, 0, MouseButton::Right),
+            KeyAction::Call("cancel_animation".to_string()),
         ],
         None,     // 无时间窗口限制
         false,    // 不屏蔽原始按键
         HashSet::new(),
     );
     
+    // 动画取消以最新的输入为准，打断还没执行完的上一次取消
+    if let Some(action) = config.action_mut("q_cancel") {
+        action.on_busy = BusyPolicy::Preempt;
+    }
+    
     // 设置切换快捷键
     config.set_hotkey(vec![Key::F2]);
     
@@ -1,5 +1,5 @@
 use std::collections::HashSet;
-use crate::config::hero::{HeroConfig, Key, KeyAction};
+use crate::config::hero::{HeroConfig, Key, KeyAction, SequenceOrder};
 
 pub fn create_config() -> HeroConfig {
     let mut config = HeroConfig::new("yasuo");
@@ -20,7 +20,7 @@ pub fn create_config() -> HeroConfig {
         Some(150), // 150ms时间窗口：在按E后150ms内按R才会触发
         true,      // 屏蔽原始的R键
         block_r,   // 屏蔽的按键列表
-    );
+    ).order = SequenceOrder::strict(); // 必须先E后R，先R后E不会触发
     
     // 添加E+D连招: 当按下E后150ms内按D时，执行EQD连招并屏蔽D
     let mut block_d = HashSet::new();
@@ -35,7 +35,7 @@ pub fn create_config() -> HeroConfig {
         Some(300), // 将时间窗口扩大到300ms，更容易触发
         true,      // 屏蔽原始的D键
         block_d,   // 屏蔽的按键列表
-    );
+    ).order = SequenceOrder::strict(); // 必须先E后D
     
     // 设置切换快捷键
     config.set_hotkey(vec![Key::F1]);
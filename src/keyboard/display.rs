@@ -0,0 +1,230 @@
+use crate::config::hero::{MousePosition, ScreenAnchor};
+
+/// 屏幕上的一块矩形区域，坐标是屏幕像素
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    fn point_at(&self, fx: f64, fy: f64) -> (i32, i32) {
+        (
+            self.x + (self.width as f64 * fx).round() as i32,
+            self.y + (self.height as f64 * fy).round() as i32,
+        )
+    }
+}
+
+/// 换算鼠标位置需要的显示信息，模拟器通过它拿到当前的屏幕、窗口和光标
+pub trait DisplayGeometry: Send {
+    /// 主屏幕的区域
+    fn screen(&self) -> Rect;
+    /// 游戏窗口客户区的区域，没有时返回None
+    fn game_window(&self) -> Option<Rect>;
+    /// 系统缩放比例，100%时为1.0
+    fn scale_factor(&self) -> f64;
+    /// 当前光标的屏幕坐标
+    fn cursor(&self) -> (i32, i32);
+}
+
+/// 把配置里的鼠标位置换算成当前的屏幕坐标
+pub fn resolve_position(position: &MousePosition, display: &dyn DisplayGeometry) -> (i32, i32) {
+    match *position {
+        MousePosition::Pixels { x, y } => (x, y),
+        MousePosition::Screen { x, y } => display.screen().point_at(x, y),
+        MousePosition::Window { x, y } => window_or_screen(display).point_at(x, y),
+        MousePosition::Cursor { dx, dy } => {
+            let (x, y) = display.cursor();
+            let scale = display.scale_factor();
+            (
+                x + (dx as f64 * scale).round() as i32,
+                y + (dy as f64 * scale).round() as i32,
+            )
+        }
+        MousePosition::Anchor { anchor, dx, dy } => {
+            let window = window_or_screen(display);
+            let (x, y) = match anchor {
+                ScreenAnchor::ScreenCenter => display.screen().point_at(0.5, 0.5),
+                ScreenAnchor::WindowCenter => window.point_at(0.5, 0.5),
+                ScreenAnchor::WindowTopLeft => window.point_at(0.0, 0.0),
+                ScreenAnchor::MinimapCorner => window.point_at(1.0, 1.0),
+            };
+            // 游戏界面按窗口高度缩放，偏移也按高度计算才能在不同宽高比下对准
+            let unit = window.height as f64;
+            (x + (dx * unit).round() as i32, y + (dy * unit).round() as i32)
+        }
+    }
+}
+
+fn window_or_screen(display: &dyn DisplayGeometry) -> Rect {
+    display.game_window().unwrap_or_else(|| display.screen())
+}
+
+/// 从系统读取显示信息，游戏窗口取当前的前台窗口
+pub struct SystemDisplay;
+
+#[cfg(windows)]
+impl DisplayGeometry for SystemDisplay {
+    fn screen(&self) -> Rect {
+        use winapi::um::winuser::{GetSystemMetrics, SM_CXSCREEN, SM_CYSCREEN};
+        
+        let (width, height) = unsafe { (GetSystemMetrics(SM_CXSCREEN), GetSystemMetrics(SM_CYSCREEN)) };
+        Rect { x: 0, y: 0, width, height }
+    }
+    
+    fn game_window(&self) -> Option<Rect> {
+        use std::mem;
+        use winapi::shared::windef::{POINT, RECT};
+        use winapi::um::winuser::{ClientToScreen, GetClientRect, GetForegroundWindow};
+        
+        unsafe {
+            let hwnd = GetForegroundWindow();
+            if hwnd.is_null() {
+                return None;
+            }
+            
+            let mut client: RECT = mem::zeroed();
+            if GetClientRect(hwnd, &mut client) == 0 {
+                return None;
+            }
+            let mut origin = POINT { x: 0, y: 0 };
+            if ClientToScreen(hwnd, &mut origin) == 0 {
+                return None;
+            }
+            
+            let width = client.right - client.left;
+            let height = client.bottom - client.top;
+            if width <= 0 || height <= 0 {
+                return None;
+            }
+            Some(Rect { x: origin.x, y: origin.y, width, height })
+        }
+    }
+    
+    fn scale_factor(&self) -> f64 {
+        use winapi::um::winuser::{GetDpiForSystem, GetDpiForWindow, GetForegroundWindow};
+        
+        let dpi = unsafe {
+            let hwnd = GetForegroundWindow();
+            let dpi = if hwnd.is_null() { 0 } else { GetDpiForWindow(hwnd) };
+            if dpi == 0 { GetDpiForSystem() } else { dpi }
+        };
+        dpi as f64 / 96.0
+    }
+    
+    fn cursor(&self) -> (i32, i32) {
+        use winapi::shared::windef::POINT;
+        use winapi::um::winuser::GetCursorPos;
+        
+        let mut point = POINT { x: 0, y: 0 };
+        unsafe { GetCursorPos(&mut point) };
+        (point.x, point.y)
+    }
+}
+
+#[cfg(not(windows))]
+impl DisplayGeometry for SystemDisplay {
+    fn screen(&self) -> Rect {
+        use enigo::{Enigo, MouseControllable};
+        
+        let (width, height) = Enigo::new().main_display_size();
+        Rect { x: 0, y: 0, width, height }
+    }
+    
+    // 其他平台上拿不到游戏窗口，按整个屏幕计算
+    fn game_window(&self) -> Option<Rect> {
+        None
+    }
+    
+    fn scale_factor(&self) -> f64 {
+        1.0
+    }
+    
+    fn cursor(&self) -> (i32, i32) {
+        use enigo::{Enigo, MouseControllable};
+        
+        Enigo::new().mouse_location()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 固定的显示信息
+    struct FakeDisplay {
+        window: Option<Rect>,
+        scale: f64,
+        cursor: (i32, i32),
+    }
+    
+    impl DisplayGeometry for FakeDisplay {
+        fn screen(&self) -> Rect {
+            Rect { x: 0, y: 0, width: 1920, height: 1080 }
+        }
+        
+        fn game_window(&self) -> Option<Rect> {
+            self.window
+        }
+        
+        fn scale_factor(&self) -> f64 {
+            self.scale
+        }
+        
+        fn cursor(&self) -> (i32, i32) {
+            self.cursor
+        }
+    }
+    
+    fn windowed() -> FakeDisplay {
+        FakeDisplay {
+            window: Some(Rect { x: 100, y: 50, width: 1280, height: 720 }),
+            scale: 1.5,
+            cursor: (400, 300),
+        }
+    }
+    
+    #[test]
+    fn pixels_are_unchanged() {
+        assert_eq!(resolve_position(&MousePosition::Pixels { x: 12, y: 34 }, &windowed()), (12, 34));
+    }
+    
+    #[test]
+    fn screen_fraction_uses_whole_screen() {
+        assert_eq!(resolve_position(&MousePosition::Screen { x: 0.5, y: 0.25 }, &windowed()), (960, 270));
+    }
+    
+    #[test]
+    fn window_fraction_uses_game_window() {
+        assert_eq!(resolve_position(&MousePosition::Window { x: 0.5, y: 0.5 }, &windowed()), (740, 410));
+        assert_eq!(resolve_position(&MousePosition::Window { x: 1.0, y: 1.0 }, &windowed()), (1380, 770));
+    }
+    
+    #[test]
+    fn cursor_offset_is_scaled() {
+        assert_eq!(resolve_position(&MousePosition::Cursor { dx: 10, dy: -20 }, &windowed()), (415, 270));
+    }
+    
+    #[test]
+    fn anchor_offset_follows_window_height() {
+        let display = windowed();
+        let anchor = |anchor, dx, dy| resolve_position(&MousePosition::Anchor { anchor, dx, dy }, &display);
+        
+        assert_eq!(anchor(ScreenAnchor::ScreenCenter, 0.0, 0.0), (960, 540));
+        assert_eq!(anchor(ScreenAnchor::WindowCenter, 0.1, -0.1), (812, 338));
+        assert_eq!(anchor(ScreenAnchor::WindowTopLeft, 0.0, 0.0), (100, 50));
+        assert_eq!(anchor(ScreenAnchor::MinimapCorner, -0.25, -0.25), (1200, 590));
+    }
+    
+    #[test]
+    fn missing_game_window_falls_back_to_screen() {
+        let display = FakeDisplay { window: None, scale: 1.0, cursor: (0, 0) };
+        
+        assert_eq!(resolve_position(&MousePosition::Window { x: 0.5, y: 0.5 }, &display), (960, 540));
+        let anchor = MousePosition::Anchor { anchor: ScreenAnchor::WindowTopLeft, dx: 0.5, dy: 0.5 };
+        assert_eq!(resolve_position(&anchor, &display), (540, 540));
+    }
+}
@@ -0,0 +1,29 @@
+use crate::config::hero::Key;
+
+/// 键盘和鼠标统一后的输入事件，鼠标按键和滚轮同样用Key表示
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputEvent {
+    pub key: Key,
+    pub is_down: bool,
+}
+
+impl InputEvent {
+    pub fn down(key: Key) -> Self {
+        Self {
+            key,
+            is_down: true,
+        }
+    }
+    
+    pub fn up(key: Key) -> Self {
+        Self {
+            key,
+            is_down: false,
+        }
+    }
+    
+    /// 滚轮滚动一格: 按下之后立即松开
+    pub fn wheel(key: Key) -> [Self; 2] {
+        [Self::down(key.clone()), Self::up(key)]
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use log::{debug, error, info};
+use device_query::{DeviceQuery, DeviceState, Keycode};
+
+use crate::config::hero::{Key, MouseButton};
+use crate::keyboard::InputEvent;
+use crate::keyboard::simulator::is_recent_injection;
+use crate::macro_engine::MacroEngine;
+
+/// 轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Linux下的输入来源: 轮询键盘和鼠标的状态，把变化转换为输入事件。
+/// 轮询只能观察不能拦截，连招的block_original在这里不起作用；
+/// 也分不出模拟器发出的输入，刚被模拟过的按键的变化直接跳过；
+/// X11的指针状态不包含侧键，滚轮只有恰好在轮询时滚动才能观察到
+pub struct PollingListener {
+    macro_engine: Arc<MacroEngine>,
+    running: Arc<AtomicBool>,
+}
+
+impl PollingListener {
+    pub fn new(macro_engine: Arc<MacroEngine>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            macro_engine,
+            running: Arc::new(AtomicBool::new(false)),
+        })
+    }
+    
+    pub fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        
+        let engine = self.macro_engine.clone();
+        let running = self.running.clone();
+        thread::Builder::new()
+            .name("input-poller".to_string())
+            .spawn(move || {
+                let device_state = DeviceState::new();
+                // 启动之前已经按下的键不产生事件，只用来校正按键状态
+                let mut previous = pressed_inputs(&device_state);
+                engine.resync_key_states(&mappable_keys(), &|key| previous.contains(key));
+                
+                while running.load(Ordering::SeqCst) {
+                    let current = pressed_inputs(&device_state);
+                    
+                    // 先处理松开再处理按下，保持和钩子收到的顺序一致
+                    for key in previous.difference(&current) {
+                        if !key.is_wheel() && !is_recent_injection(key) {
+                            engine.process_input(InputEvent::up(key.clone()));
+                        }
+                    }
+                    for key in current.difference(&previous) {
+                        if is_recent_injection(key) {
+                            continue;
+                        }
+                        debug!("轮询到按下: {:?}", key);
+                        if key.is_wheel() {
+                            for event in InputEvent::wheel(key.clone()) {
+                                engine.process_input(event);
+                            }
+                        } else {
+                            engine.process_input(InputEvent::down(key.clone()));
+                        }
+                    }
+                    
+                    previous = current;
+                    thread::sleep(POLL_INTERVAL);
+                }
+                
+                debug!("输入轮询线程退出");
+            })
+            .map_err(|e| {
+                error!("无法启动输入轮询线程: {}", e);
+                self.running.store(false, Ordering::SeqCst);
+                e
+            })?;
+        
+        info!("输入轮询已启动");
+        Ok(())
+    }
+    
+    pub fn stop(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.running.store(false, Ordering::SeqCst);
+        info!("输入轮询已停止");
+        Ok(())
+    }
+}
+
+/// 当前按住的键盘按键和鼠标按键
+fn pressed_inputs(device_state: &DeviceState) -> HashSet<Key> {
+    let mut pressed: HashSet<Key> = device_state.get_keys().iter()
+        .filter_map(keycode_to_key)
+        .collect();
+    
+    // X11的按钮编号: 1左键 2中键 3右键 4/5滚轮
+    let mouse = device_state.get_mouse();
+    let buttons = [
+        (1, Key::Mouse(MouseButton::Left)),
+        (2, Key::Mouse(MouseButton::Middle)),
+        (3, Key::Mouse(MouseButton::Right)),
+        (4, Key::WheelUp),
+        (5, Key::WheelDown),
+    ];
+    for (index, key) in buttons {
+        if mouse.button_pressed.get(index).copied().unwrap_or(false) {
+            pressed.insert(key);
+        }
+    }
+    
+    pressed
+}
+
+/// 可以轮询到状态的所有按键，和pressed_inputs能产生的按键一致
+fn mappable_keys() -> Vec<Key> {
+    let mut keys = vec![Key::Escape, Key::Tab, Key::CapsLock, Key::Space, Key::Enter];
+    keys.extend(Key::MODIFIER_SIDES);
+    keys.extend([MouseButton::Left, MouseButton::Right, MouseButton::Middle].map(Key::Mouse));
+    keys.extend([
+        Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6,
+        Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    ]);
+    keys.extend(('0'..='9').chain('a'..='z').map(Key::Character));
+    keys
+}
+
+fn keycode_to_key(keycode: &Keycode) -> Option<Key> {
+    let key = match keycode {
+        Keycode::Escape => Key::Escape,
+        Keycode::Tab => Key::Tab,
+        Keycode::CapsLock => Key::CapsLock,
+        Keycode::LShift => Key::LShift,
+        Keycode::RShift => Key::RShift,
+        Keycode::LControl => Key::LControl,
+        Keycode::RControl => Key::RControl,
+        Keycode::LAlt => Key::LAlt,
+        Keycode::RAlt => Key::RAlt,
+        Keycode::Space => Key::Space,
+        Keycode::Enter => Key::Enter,
+        Keycode::F1 => Key::F1,
+        Keycode::F2 => Key::F2,
+        Keycode::F3 => Key::F3,
+        Keycode::F4 => Key::F4,
+        Keycode::F5 => Key::F5,
+        Keycode::F6 => Key::F6,
+        Keycode::F7 => Key::F7,
+        Keycode::F8 => Key::F8,
+        Keycode::F9 => Key::F9,
+        Keycode::F10 => Key::F10,
+        Keycode::F11 => Key::F11,
+        Keycode::F12 => Key::F12,
+        Keycode::Key0 => Key::Character('0'),
+        Keycode::Key1 => Key::Character('1'),
+        Keycode::Key2 => Key::Character('2'),
+        Keycode::Key3 => Key::Character('3'),
+        Keycode::Key4 => Key::Character('4'),
+        Keycode::Key5 => Key::Character('5'),
+        Keycode::Key6 => Key::Character('6'),
+        Keycode::Key7 => Key::Character('7'),
+        Keycode::Key8 => Key::Character('8'),
+        Keycode::Key9 => Key::Character('9'),
+        Keycode::A => Key::Character('a'),
+        Keycode::B => Key::Character('b'),
+        Keycode::C => Key::Character('c'),
+        Keycode::D => Key::Character('d'),
+        Keycode::E => Key::Character('e'),
+        Keycode::F => Key::Character('f'),
+        Keycode::G => Key::Character('g'),
+        Keycode::H => Key::Character('h'),
+        Keycode::I => Key::Character('i'),
+        Keycode::J => Key::Character('j'),
+        Keycode::K => Key::Character('k'),
+        Keycode::L => Key::Character('l'),
+        Keycode::M => Key::Character('m'),
+        Keycode::N => Key::Character('n'),
+        Keycode::O => Key::Character('o'),
+        Keycode::P => Key::Character('p'),
+        Keycode::Q => Key::Character('q'),
+        Keycode::R => Key::Character('r'),
+        Keycode::S => Key::Character('s'),
+        Keycode::T => Key::Character('t'),
+        Keycode::U => Key::Character('u'),
+        Keycode::V => Key::Character('v'),
+        Keycode::W => Key::Character('w'),
+        Keycode::X => Key::Character('x'),
+        Keycode::Y => Key::Character('y'),
+        Keycode::Z => Key::Character('z'),
+        _ => return None,
+    };
+    Some(key)
+}
@@ -3,12 +3,14 @@ use std::time::{Duration, Instant};
 use std::thread;
 use log::{debug, error, info};
 use winapi::um::winuser;
-use winapi::shared::minwindef::{WPARAM, LPARAM, LRESULT, DWORD};
-use winapi::shared::windef::{HHOOK, HWND};
+use winapi::shared::minwindef::{WPARAM, LPARAM, LRESULT, DWORD, HINSTANCE};
+use winapi::shared::windef::{HHOOK, HWND, HWINEVENTHOOK};
+use winapi::shared::ntdef::LONG;
 use std::cell::Cell;
 use std::sync::atomic::{AtomicBool, Ordering};
 use crate::macro_engine::MacroEngine;
-use crate::config::hero::Key;
+use crate::config::hero::{Key, MouseButton};
+use crate::keyboard::InputEvent;
 use std::sync::mpsc::{channel, Sender, Receiver};
 use winapi::um::libloaderapi::{LoadLibraryA, GetProcAddress};
 use std::ffi::CString;
@@ -17,12 +19,16 @@ use std::ffi::CString;
 thread_local! {
     static HOOK_HANDLE: Cell<Option<HHOOK>> = Cell::new(None);
     static MACRO_ENGINE: Cell<Option<Arc<MacroEngine>>> = Cell::new(None);
+    static FOREGROUND_HOOK: Cell<Option<HWINEVENTHOOK>> = Cell::new(None);
 }
 
 static HOOK_RUNNING: AtomicBool = AtomicBool::new(false);
 
 type InstallHookFn = unsafe extern "system" fn(*mut i32) -> bool;
 type UninstallHookFn = unsafe extern "system" fn() -> bool;
+/// 鼠标钩子的回调: 消息、mouseData、标志，返回true时屏蔽这个鼠标事件
+type MouseCallback = extern "system" fn(u32, DWORD, DWORD) -> bool;
+type InstallMouseHookFn = unsafe extern "system" fn(MouseCallback) -> bool;
 
 pub struct KeyboardListener {
     macro_engine: Arc<MacroEngine>,
@@ -59,14 +65,46 @@ impl KeyboardListener {
                 return Err("Failed to install keyboard hook".into());
             }
             
+            // 鼠标按键和滚轮同样可以触发连招，钩子回调在当前线程上直接交给宏引擎
+            let install_mouse_hook: InstallMouseHookFn = std::mem::transmute(
+                GetProcAddress(handle, CString::new("install_mouse_hook")?.as_ptr())
+            );
+            if !install_mouse_hook(mouse_hook_callback) {
+                error!("无法安装鼠标钩子，鼠标按键不会触发连招");
+            }
+            
             *self.dll_handle.lock().unwrap() = Some(handle);
+            
+            // 切换前台窗口期间的按键事件不会经过钩子，切回时重新校正按键状态
+            let foreground_hook = winuser::SetWinEventHook(
+                winuser::EVENT_SYSTEM_FOREGROUND,
+                winuser::EVENT_SYSTEM_FOREGROUND,
+                std::ptr::null_mut(),
+                Some(foreground_event_proc),
+                0,
+                0,
+                winuser::WINEVENT_OUTOFCONTEXT,
+            );
+            if foreground_hook.is_null() {
+                error!("无法监听前台窗口切换，切换窗口后按键状态可能不准确");
+            } else {
+                FOREGROUND_HOOK.with(|cell| cell.set(Some(foreground_hook)));
+            }
         }
         
+        MACRO_ENGINE.with(|cell| cell.set(Some(self.macro_engine.clone())));
+        HOOK_RUNNING.store(true, Ordering::SeqCst);
+        
+        // 钩子安装之前按下的键没有记录，以实际状态为准
+        self.macro_engine.resync_key_states(&mappable_keys(), &is_key_physically_down);
+        
         info!("键盘监听器已启动");
         Ok(())
     }
     
     pub fn stop(&self) -> Result<(), Box<dyn std::error::Error>> {
+        HOOK_RUNNING.store(false, Ordering::SeqCst);
+        
         unsafe {
             if let Some(handle) = *self.dll_handle.lock().unwrap() {
                 let uninstall_hook: UninstallHookFn = std::mem::transmute(
@@ -76,6 +114,15 @@ impl KeyboardListener {
                 if !uninstall_hook() {
                     return Err("Failed to uninstall keyboard hook".into());
                 }
+                
+                let uninstall_mouse_hook: UninstallHookFn = std::mem::transmute(
+                    GetProcAddress(handle, CString::new("uninstall_mouse_hook")?.as_ptr())
+                );
+                uninstall_mouse_hook();
+            }
+            
+            if let Some(foreground_hook) = FOREGROUND_HOOK.with(|cell| cell.take()) {
+                winuser::UnhookWinEvent(foreground_hook);
             }
         }
         
@@ -108,6 +155,11 @@ unsafe extern "system" fn keyboard_hook_proc(code: i32, wparam: WPARAM, lparam:
     if !is_keydown && !is_keyup {
         return winuser::CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam);
     }
+
+    // 模拟器发出的按键(连招动作、补发的暂扣按键)直接放行，不再参与匹配
+    if key_info.flags & winuser::LLKHF_INJECTED != 0 {
+        return winuser::CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam);
+    }
     
     let key = virtual_key_to_key(key_code);
     
@@ -137,6 +189,155 @@ unsafe extern "system" fn keyboard_hook_proc(code: i32, wparam: WPARAM, lparam:
     winuser::CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
 }
 
+// 鼠标钩子DLL的回调函数，返回true时屏蔽这个鼠标事件
+extern "system" fn mouse_hook_callback(message: u32, mouse_data: DWORD, flags: DWORD) -> bool {
+    if !HOOK_RUNNING.load(Ordering::SeqCst) {
+        return false;
+    }
+    
+    // 模拟器发出的鼠标事件直接放行
+    if flags & winuser::LLMHF_INJECTED != 0 {
+        return false;
+    }
+    
+    let events = mouse_message_to_events(message, mouse_data);
+    if events.is_empty() {
+        return false;
+    }
+    
+    debug!("鼠标事件: {:?}", events);
+    
+    // 滚轮的按下和松开来自同一个消息，按下是否被屏蔽决定整个消息
+    let mut handled = false;
+    MACRO_ENGINE.with(|cell| {
+        if let Some(engine) = cell.take() {
+            for event in events {
+                let wheel_release = event.key.is_wheel() && !event.is_down;
+                let blocked = engine.process_input(event);
+                if !wheel_release {
+                    handled = blocked;
+                }
+            }
+            cell.set(Some(engine));
+        }
+    });
+    
+    if handled {
+        debug!("鼠标事件被屏蔽");
+    }
+    handled
+}
+
+/// 把低级鼠标钩子的消息转换为输入事件，移动等不关心的消息返回空
+fn mouse_message_to_events(message: u32, mouse_data: DWORD) -> Vec<InputEvent> {
+    // 侧键和滚轮的具体信息在mouseData的高16位
+    let high_word = (mouse_data >> 16) as u16;
+    let x_button = || match high_word {
+        winuser::XBUTTON1 => Some(MouseButton::X1),
+        winuser::XBUTTON2 => Some(MouseButton::X2),
+        _ => None,
+    };
+    
+    match message {
+        winuser::WM_LBUTTONDOWN => vec![InputEvent::down(Key::Mouse(MouseButton::Left))],
+        winuser::WM_LBUTTONUP => vec![InputEvent::up(Key::Mouse(MouseButton::Left))],
+        winuser::WM_RBUTTONDOWN => vec![InputEvent::down(Key::Mouse(MouseButton::Right))],
+        winuser::WM_RBUTTONUP => vec![InputEvent::up(Key::Mouse(MouseButton::Right))],
+        winuser::WM_MBUTTONDOWN => vec![InputEvent::down(Key::Mouse(MouseButton::Middle))],
+        winuser::WM_MBUTTONUP => vec![InputEvent::up(Key::Mouse(MouseButton::Middle))],
+        winuser::WM_XBUTTONDOWN => x_button().map(|button| InputEvent::down(Key::Mouse(button))).into_iter().collect(),
+        winuser::WM_XBUTTONUP => x_button().map(|button| InputEvent::up(Key::Mouse(button))).into_iter().collect(),
+        winuser::WM_MOUSEWHEEL => {
+            let key = if high_word as i16 > 0 { Key::WheelUp } else { Key::WheelDown };
+            InputEvent::wheel(key).to_vec()
+        }
+        _ => Vec::new(),
+    }
+}
+
+// 前台窗口切换的回调函数
+unsafe extern "system" fn foreground_event_proc(
+    _hook: HWINEVENTHOOK,
+    _event: DWORD,
+    _hwnd: HWND,
+    _id_object: LONG,
+    _id_child: LONG,
+    _event_thread: DWORD,
+    _event_time: DWORD,
+) {
+    debug!("前台窗口已切换，校正按键状态");
+    
+    MACRO_ENGINE.with(|cell| {
+        if let Some(engine) = cell.take() {
+            engine.resync_key_states(&mappable_keys(), &is_key_physically_down);
+            cell.set(Some(engine));
+        }
+    });
+}
+
+fn is_key_physically_down(key: &Key) -> bool {
+    match key_to_virtual_key(key) {
+        Some(vk) => unsafe { winuser::GetAsyncKeyState(vk) as u16 & 0x8000 != 0 },
+        None => false,
+    }
+}
+
+/// 可以查询实际状态的所有按键；修饰键只取左右两侧，和钩子收到的按键一致
+fn mappable_keys() -> Vec<Key> {
+    let mut keys = vec![Key::Escape, Key::Tab, Key::CapsLock, Key::Space, Key::Enter];
+    keys.extend(Key::MODIFIER_SIDES);
+    keys.extend([MouseButton::Left, MouseButton::Right, MouseButton::Middle, MouseButton::X1, MouseButton::X2].map(Key::Mouse));
+    keys.extend([
+        Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6,
+        Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    ]);
+    keys.extend(('0'..='9').chain('a'..='z').map(Key::Character));
+    keys
+}
+
+fn key_to_virtual_key(key: &Key) -> Option<i32> {
+    let vk = match key {
+        Key::Escape => 0x1B,
+        Key::Tab => 0x09,
+        Key::CapsLock => 0x14,
+        Key::Shift => 0x10,
+        Key::Control => 0x11,
+        Key::Alt => 0x12,
+        Key::Space => 0x20,
+        Key::Enter => 0x0D,
+        Key::Mouse(MouseButton::Left) => 0x01,
+        Key::Mouse(MouseButton::Right) => 0x02,
+        Key::Mouse(MouseButton::Middle) => 0x04,
+        Key::Mouse(MouseButton::X1) => 0x05,
+        Key::Mouse(MouseButton::X2) => 0x06,
+        // 滚轮没有按住的状态
+        Key::WheelUp | Key::WheelDown => return None,
+        Key::LShift => 0xA0,
+        Key::RShift => 0xA1,
+        Key::LControl => 0xA2,
+        Key::RControl => 0xA3,
+        Key::LAlt => 0xA4,
+        Key::RAlt => 0xA5,
+        Key::F1 => 0x70,
+        Key::F2 => 0x71,
+        Key::F3 => 0x72,
+        Key::F4 => 0x73,
+        Key::F5 => 0x74,
+        Key::F6 => 0x75,
+        Key::F7 => 0x76,
+        Key::F8 => 0x77,
+        Key::F9 => 0x78,
+        Key::F10 => 0x79,
+        Key::F11 => 0x7A,
+        Key::F12 => 0x7B,
+        Key::Character(c @ '0'..='9') => *c as i32,
+        Key::Character(c @ 'a'..='z') => c.to_ascii_uppercase() as i32,
+        Key::Character(c @ 'A'..='Z') => *c as i32,
+        Key::Character(_) => return None,
+    };
+    Some(vk)
+}
+
 fn virtual_key_to_key(vk: u32) -> Key {
     let key = match vk {
         0x1B => Key::Escape,
@@ -147,6 +348,13 @@ fn virtual_key_to_key(vk: u32) -> Key {
         0x12 => Key::Alt,
         0x20 => Key::Space,
         0x0D => Key::Enter,
+        // 低级钩子收到的修饰键区分左右
+        0xA0 => Key::LShift,
+        0xA1 => Key::RShift,
+        0xA2 => Key::LControl,
+        0xA3 => Key::RControl,
+        0xA4 => Key::LAlt,
+        0xA5 => Key::RAlt,
         // 功能键
         0x70 => Key::F1,
         0x71 => Key::F2,
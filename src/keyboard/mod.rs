@@ -1,5 +1,13 @@
+pub mod display;
+pub mod input;
+#[cfg(windows)]
 pub mod listener;
+#[cfg(target_os = "linux")]
+pub mod linux;
 pub mod simulator;
 
+pub use input::InputEvent;
+#[cfg(windows)]
 pub use listener::KeyboardListener;
-pub use simulator::KeyboardSimulator; 
\ No newline at end of file
+#[cfg(target_os = "linux")]
+pub use linux::PollingListener as KeyboardListener;
\ No newline at end of file
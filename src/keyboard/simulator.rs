@@ -1,54 +1,358 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use std::thread;
-use log::{debug, error, info};
+use once_cell::sync::Lazy;
+use log::{debug, error, info, warn};
 use enigo::{Enigo, Key as EnigoKey, KeyboardControllable, MouseControllable, MouseButton as EnigoMouseButton};
-use crate::config::hero::{Key, KeyAction, MouseButton};
+use crate::config::hero::{ActionTiming, Key, KeyAction, MouseButton, MousePosition};
+use crate::keyboard::display::{resolve_position, DisplayGeometry, SystemDisplay};
+
+/// 等待期间检查取消的间隔
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(5);
+
+/// WhileHeld每轮至少间隔的时间，防止没有等待的循环体占满CPU
+const WHILE_HELD_MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 模拟一个键之后这么久之内观察到它的变化，都当作模拟器自己发出的
+const INJECTION_ECHO: Duration = Duration::from_millis(50);
+
+/// 最近模拟过的按键。轮询方式的监听分不出实际按键和模拟按键，靠它跳过模拟器自己的输入
+static RECENT_INJECTIONS: Lazy<Mutex<HashMap<Key, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 这个键最近是否被模拟器按下或松开过
+#[cfg(target_os = "linux")]
+pub fn is_recent_injection(key: &Key) -> bool {
+    RECENT_INJECTIONS.lock().unwrap()
+        .get(key)
+        .is_some_and(|at| at.elapsed() < INJECTION_ECHO)
+}
+
+fn record_injection(key: &Key) {
+    let now = Instant::now();
+    let mut injections = RECENT_INJECTIONS.lock().unwrap();
+    injections.retain(|_, at| now.duration_since(*at) < INJECTION_ECHO);
+    injections.insert(key.clone(), now);
+}
+
+/// 令牌桶限速: 最多允许一秒的量突发，之后按固定速率补充
+struct RateLimiter {
+    per_second: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(per_second: u32) -> Self {
+        let per_second = per_second.max(1);
+        Self {
+            per_second,
+            tokens: per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+    
+    /// 取一个令牌，不够时返回需要等待的时间
+    fn try_acquire(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second as f64).min(self.per_second as f64);
+        self.last_refill = now;
+        
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.per_second as f64))
+        }
+    }
+}
 
 pub struct KeyboardSimulator {
     enigo: Enigo,
+    /// 当前动作序列通过Down按下、还没有Up的按键
+    held_keys: Vec<Key>,
+    /// 模拟输入的全局速率限制
+    rate_limiter: Option<RateLimiter>,
+    /// 换算鼠标位置用的显示信息
+    display: Box<dyn DisplayGeometry>,
+    /// 查询用户是否按住某个键
+    is_key_down: Box<dyn Fn(&Key) -> bool + Send>,
 }
 
 impl KeyboardSimulator {
     pub fn new() -> Self {
+        Self::with_display(Box::new(SystemDisplay))
+    }
+    
+    /// 使用指定的显示信息换算鼠标位置
+    pub fn with_display(display: Box<dyn DisplayGeometry>) -> Self {
         Self {
             enigo: Enigo::new(),
+            held_keys: Vec::new(),
+            rate_limiter: None,
+            display,
+            is_key_down: Box::new(|_| false),
         }
     }
     
-    pub fn execute_actions(&mut self, actions: &[KeyAction]) {
+    /// 设置查询用户按键状态的方式，没有设置时WhileHeld一次也不执行
+    pub fn set_key_state(&mut self, is_key_down: Box<dyn Fn(&Key) -> bool + Send>) {
+        self.is_key_down = is_key_down;
+    }
+    
+    /// 限制每秒最多发出的模拟输入数，None表示不限制
+    pub fn set_rate_limit(&mut self, per_second: Option<u32>) {
+        self.rate_limiter = per_second.map(RateLimiter::new);
+    }
+    
+    /// 执行动作序列，每个动作之前和等待期间检查是否被取消；被取消时返回false
+    pub fn execute_actions_until(&mut self, actions: &[KeyAction], timing: ActionTiming, cancelled: &dyn Fn() -> bool) -> bool {
         info!("开始执行键盘动作序列: {:?}", actions);
         
-        for action in actions {
+        // 只记录这一次按下的键，之前的序列有意留着按下的键不归这次取消时松开
+        self.held_keys.clear();
+        if !self.run_actions(actions, timing, cancelled) {
+            return false;
+        }
+        
+        info!("键盘动作序列执行完成");
+        true
+    }
+    
+    /// 依次执行动作，Repeat和WhileHeld的循环体同样由这里执行
+    fn run_actions(&mut self, actions: &[KeyAction], timing: ActionTiming, cancelled: &dyn Fn() -> bool) -> bool {
+        for (index, action) in actions.iter().enumerate() {
+            if index > 0 && timing.action_gap > 0 && !self.sleep_unless(timing.action_gap, cancelled) {
+                return false;
+            }
+            
+            if cancelled() {
+                info!("键盘动作序列被取消");
+                return false;
+            }
+            
+            // 等待和控制结构本身不算作模拟输入
+            let is_input = !matches!(action,
+                KeyAction::Delay(_) | KeyAction::Repeat { .. } | KeyAction::WhileHeld { .. } | KeyAction::Call(_));
+            if is_input && !self.throttle(cancelled) {
+                return false;
+            }
+            
             match action {
                 KeyAction::Press(key) => {
                     info!("模拟按下并释放键: {:?}", key);
-                    self.press_key(key);
-                    thread::sleep(Duration::from_millis(50));
+                    if !self.hold_key(key, timing.press_duration, cancelled) {
+                        return false;
+                    }
+                },
+                &KeyAction::Hold(ref key, ms) => {
+                    info!("模拟按住键 {:?} {}ms", key, ms);
+                    if !self.hold_key(key, ms, cancelled) {
+                        return false;
+                    }
                 },
                 &KeyAction::Delay(ms) => {
                     info!("延迟 {}ms", ms);
-                    thread::sleep(Duration::from_millis(ms as u64));
+                    if !self.sleep_unless(ms, cancelled) {
+                        return false;
+                    }
+                },
+                &KeyAction::MouseClick(dx, dy, ref button) => {
+                    info!("模拟鼠标点击: 偏移({}, {}), 按钮: {:?}", dx, dy, button);
+                    self.mouse_click(&MousePosition::Cursor { dx, dy }, button);
+                },
+                KeyAction::MouseClickAt(position, button) => {
+                    info!("模拟鼠标点击: 位置{:?}, 按钮: {:?}", position, button);
+                    self.mouse_click(position, button);
+                },
+                &KeyAction::MouseMoveBy(dx, dy) => {
+                    info!("模拟鼠标移动: 偏移({}, {})", dx, dy);
+                    self.mouse_move(&MousePosition::Cursor { dx, dy });
+                },
+                KeyAction::MouseMoveTo(position) => {
+                    info!("模拟鼠标移动: 位置{:?}", position);
+                    self.mouse_move(position);
+                },
+                KeyAction::MouseDown(button) => {
+                    info!("模拟按下鼠标按键: {:?}", button);
+                    let key = Key::Mouse(button.clone());
+                    self.press_key(&key);
+                    self.held_keys.push(key);
+                },
+                KeyAction::MouseUp(button) => {
+                    info!("模拟松开鼠标按键: {:?}", button);
+                    let key = Key::Mouse(button.clone());
+                    self.release_key(&key);
+                    self.held_keys.retain(|held| held != &key);
                 },
-                &KeyAction::MouseClick(x, y, ref button) => {
-                    info!("模拟鼠标点击: 位置({}, {}), 按钮: {:?}", x, y, button);
-                    self.mouse_click(x, y, button);
+                &KeyAction::MouseDrag(dx, dy, ref button) => {
+                    info!("模拟鼠标拖动: 偏移({}, {}), 按钮: {:?}", dx, dy, button);
+                    self.mouse_drag(&MousePosition::Cursor { dx, dy }, button);
+                },
+                &KeyAction::Scroll(lines) => {
+                    info!("模拟滚轮滚动 {} 格", lines);
+                    self.enigo.mouse_scroll_y(lines);
+                },
+                &KeyAction::TypeText { ref text, open_chat, send, char_delay } => {
+                    info!("模拟输入文字: {}", text);
+                    if open_chat && !self.hold_key(&Key::Enter, timing.press_duration, cancelled) {
+                        return false;
+                    }
+                    if !self.type_text(text, char_delay, timing, cancelled) {
+                        return false;
+                    }
+                    if send && !self.hold_key(&Key::Enter, timing.press_duration, cancelled) {
+                        return false;
+                    }
+                },
+                &KeyAction::Repeat { count, ref body } => {
+                    debug!("重复执行 {} 次", count);
+                    for _ in 0..count {
+                        if !self.run_actions(body, timing, cancelled) {
+                            return false;
+                        }
+                    }
+                },
+                KeyAction::WhileHeld { key, body } => {
+                    debug!("按住 {:?} 期间反复执行", key);
+                    while (self.is_key_down)(key) {
+                        let started = Instant::now();
+                        if !self.run_actions(body, timing, cancelled) {
+                            return false;
+                        }
+                        let rest = WHILE_HELD_MIN_INTERVAL.saturating_sub(started.elapsed());
+                        if !self.sleep_unless(rest.as_millis() as u64, cancelled) {
+                            return false;
+                        }
+                    }
+                },
+                KeyAction::Call(name) => {
+                    // 编译时已经展开，剩下的只有不存在或递归的调用
+                    error!("无法调用连招: {}", name);
                 },
                 KeyAction::Down(key) => {
                     info!("模拟按下键: {:?}", key);
                     self.press_key(key);
+                    self.held_keys.push(key.clone());
                 },
                 KeyAction::Up(key) => {
                     info!("模拟释放键: {:?}", key);
                     self.release_key(key);
+                    self.held_keys.retain(|held| held != key);
                 },
             }
         }
         
-        info!("键盘动作序列执行完成");
+        true
+    }
+    
+    /// 按速率限制等待可以发出下一个输入，期间被取消时返回false
+    fn throttle(&mut self, cancelled: &dyn Fn() -> bool) -> bool {
+        loop {
+            let wait = match self.rate_limiter.as_mut() {
+                Some(limiter) => limiter.try_acquire(),
+                None => None,
+            };
+            match wait {
+                None => return true,
+                Some(wait) => {
+                    debug!("模拟输入超过速率限制，等待 {}ms", wait.as_millis());
+                    if !self.sleep_unless(wait.as_millis() as u64 + 1, cancelled) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+    
+    /// 按下按键，等待指定毫秒后松开；等待期间被取消时按键留给release_held_keys松开
+    fn hold_key(&mut self, key: &Key, ms: u64, cancelled: &dyn Fn() -> bool) -> bool {
+        self.press_key(key);
+        self.held_keys.push(key.clone());
+        
+        if !self.sleep_unless(ms, cancelled) {
+            return false;
+        }
+        
+        self.release_key(key);
+        if let Some(index) = self.held_keys.iter().rposition(|held| held == key) {
+            self.held_keys.remove(index);
+        }
+        true
+    }
+    
+    /// 逐个输入文字中的字符和转义的按键，期间被取消时返回false
+    fn type_text(&mut self, text: &str, char_delay: u64, timing: ActionTiming, cancelled: &dyn Fn() -> bool) -> bool {
+        for piece in parse_text(text) {
+            // 第一个字符之前同样等待，给打开的聊天框留出时间
+            if !self.sleep_unless(char_delay, cancelled) {
+                return false;
+            }
+            if !self.throttle(cancelled) {
+                return false;
+            }
+            
+            match piece {
+                TextPiece::Char(c) => {
+                    record_injection(&Key::Character(c.to_ascii_lowercase()));
+                    let mut buffer = [0u8; 4];
+                    self.enigo.key_sequence(c.encode_utf8(&mut buffer));
+                },
+                TextPiece::Key(key) => {
+                    if !self.hold_key(&key, timing.press_duration, cancelled) {
+                        return false;
+                    }
+                },
+            }
+        }
+        true
+    }
+    
+    /// 等待指定毫秒，期间被取消时提前返回false
+    fn sleep_unless(&self, ms: u64, cancelled: &dyn Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_millis(ms);
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            if cancelled() {
+                info!("等待期间被取消");
+                return false;
+            }
+            thread::sleep(CANCEL_CHECK_INTERVAL.min(deadline - now));
+        }
+    }
+    
+    /// 松开当前动作序列通过Down按下还没有松开的按键
+    pub fn release_held_keys(&mut self) {
+        for key in std::mem::take(&mut self.held_keys) {
+            info!("释放被取消连招按下的键: {:?}", key);
+            self.release_key(&key);
+        }
     }
     
     fn press_key(&mut self, key: &Key) {
         debug!("按下键: {:?}", key);
+        record_injection(key);
+        match key {
+            Key::Mouse(button) => {
+                if let Some(button) = self.mouse_button_to_enigo(button) {
+                    self.enigo.mouse_down(button);
+                }
+                return;
+            }
+            Key::WheelUp => return self.enigo.mouse_scroll_y(-1),
+            Key::WheelDown => return self.enigo.mouse_scroll_y(1),
+            // 需要Shift的符号没有对应的单个按键，按下时直接输入字符
+            Key::Character(c) if unshifted_key(*c).is_none() && !c.is_control() => {
+                let mut buffer = [0u8; 4];
+                return self.enigo.key_sequence(c.encode_utf8(&mut buffer));
+            }
+            _ => {}
+        }
+        
         match self.key_to_enigo_key(key) {
             Some(enigo_key) => {
                 self.enigo.key_down(enigo_key);
@@ -61,6 +365,21 @@ impl KeyboardSimulator {
     
     fn release_key(&mut self, key: &Key) {
         debug!("释放键: {:?}", key);
+        record_injection(key);
+        match key {
+            Key::Mouse(button) => {
+                if let Some(button) = self.mouse_button_to_enigo(button) {
+                    self.enigo.mouse_up(button);
+                }
+                return;
+            }
+            // 滚轮在按下时已经滚动过
+            Key::WheelUp | Key::WheelDown => return,
+            // 直接输入的字符在按下时已经输入完成
+            Key::Character(c) if unshifted_key(*c).is_none() && !c.is_control() => return,
+            _ => {}
+        }
+        
         match self.key_to_enigo_key(key) {
             Some(enigo_key) => {
                 self.enigo.key_up(enigo_key);
@@ -71,18 +390,25 @@ impl KeyboardSimulator {
         }
     }
     
-    fn mouse_click(&mut self, x: i32, y: i32, button: &MouseButton) {
+    /// 光标移动到指定位置
+    fn mouse_move(&mut self, position: &MousePosition) {
+        let (x, y) = resolve_position(position, self.display.as_ref());
+        self.enigo.mouse_move_to(x, y);
+    }
+    
+    /// 在指定位置点击，点击后光标回到原位
+    fn mouse_click(&mut self, position: &MousePosition, button: &MouseButton) {
         // 保存当前鼠标位置
         let (curr_x, curr_y) = self.enigo.mouse_location();
         
         // 移动鼠标
+        let (x, y) = resolve_position(position, self.display.as_ref());
         self.enigo.mouse_move_to(x, y);
         
         // 点击
-        match button {
-            MouseButton::Left => self.enigo.mouse_click(EnigoMouseButton::Left),
-            MouseButton::Right => self.enigo.mouse_click(EnigoMouseButton::Right),
-            MouseButton::Middle => self.enigo.mouse_click(EnigoMouseButton::Middle),
+        if let Some(enigo_button) = self.mouse_button_to_enigo(button) {
+            record_injection(&Key::Mouse(button.clone()));
+            self.enigo.mouse_click(enigo_button);
         }
         
         // 移回原位置
@@ -91,14 +417,39 @@ impl KeyboardSimulator {
         debug!("模拟鼠标点击: 位置({}, {}), 按钮: {:?}", x, y, button);
     }
     
+    /// 在当前位置按住按键，移动到指定位置后松开，光标停在终点
+    fn mouse_drag(&mut self, position: &MousePosition, button: &MouseButton) {
+        let enigo_button = match self.mouse_button_to_enigo(button) {
+            Some(enigo_button) => enigo_button,
+            None => return,
+        };
+        
+        let (x, y) = resolve_position(position, self.display.as_ref());
+        record_injection(&Key::Mouse(button.clone()));
+        self.enigo.mouse_down(enigo_button);
+        self.enigo.mouse_move_to(x, y);
+        self.enigo.mouse_up(enigo_button);
+        
+        debug!("模拟鼠标拖动: 终点({}, {}), 按钮: {:?}", x, y, button);
+    }
+    
+    fn mouse_button_to_enigo(&self, button: &MouseButton) -> Option<EnigoMouseButton> {
+        match button {
+            MouseButton::Left => Some(EnigoMouseButton::Left),
+            MouseButton::Right => Some(EnigoMouseButton::Right),
+            MouseButton::Middle => Some(EnigoMouseButton::Middle),
+            // enigo不支持侧键
+            MouseButton::X1 | MouseButton::X2 => {
+                error!("无法模拟鼠标侧键: {:?}", button);
+                None
+            }
+        }
+    }
+    
     fn key_to_enigo_key(&self, key: &Key) -> Option<EnigoKey> {
         match key {
-            Key::Character(c) => {
-                match c {
-                    'a'..='z' | 'A'..='Z' | '0'..='9' => Some(EnigoKey::Layout(*c)),
-                    _ => None,
-                }
-            },
+            // 只有不需要Shift的字符按当前键盘布局映射，带着Shift的键码会让enigo发出错误的扫描码
+            Key::Character(c) => unshifted_key(*c).map(EnigoKey::Layout),
             Key::F1 => Some(EnigoKey::F1),
             Key::F2 => Some(EnigoKey::F2),
             Key::F3 => Some(EnigoKey::F3),
@@ -119,6 +470,212 @@ impl KeyboardSimulator {
             Key::Alt => Some(EnigoKey::Alt),
             Key::Space => Some(EnigoKey::Space),
             Key::Enter => Some(EnigoKey::Return),
+            // enigo没有区分左右的修饰键，直接使用虚拟键码
+            Key::LShift => Some(EnigoKey::Raw(0xA0)),
+            Key::RShift => Some(EnigoKey::Raw(0xA1)),
+            Key::LControl => Some(EnigoKey::Raw(0xA2)),
+            Key::RControl => Some(EnigoKey::Raw(0xA3)),
+            Key::LAlt => Some(EnigoKey::Raw(0xA4)),
+            Key::RAlt => Some(EnigoKey::Raw(0xA5)),
+            // 鼠标按键和滚轮不经过键盘模拟
+            Key::Mouse(_) | Key::WheelUp | Key::WheelDown => None,
+        }
+    }
+} 
+
+/// 模拟输入的去处，宏引擎和执行线程只通过它发出动作，测试时可以换成记录动作的实现
+pub trait ActionOutput: Send {
+    /// 执行动作序列，被取消时返回false
+    fn execute_actions_until(&mut self, actions: &[KeyAction], timing: ActionTiming, cancelled: &dyn Fn() -> bool) -> bool;
+    
+    /// 松开被取消的动作序列按下还没有松开的按键
+    fn release_held_keys(&mut self);
+    
+    fn execute_actions(&mut self, actions: &[KeyAction]) {
+        self.execute_actions_until(actions, ActionTiming::default(), &|| false);
+    }
+}
+
+impl ActionOutput for KeyboardSimulator {
+    fn execute_actions_until(&mut self, actions: &[KeyAction], timing: ActionTiming, cancelled: &dyn Fn() -> bool) -> bool {
+        KeyboardSimulator::execute_actions_until(self, actions, timing, cancelled)
+    }
+    
+    fn release_held_keys(&mut self) {
+        KeyboardSimulator::release_held_keys(self)
+    }
+}
+
+/// 不按Shift就能按出的字符对应的按键字符；大写字母和小写字母是同一个键
+fn unshifted_key(c: char) -> Option<char> {
+    match c {
+        'a'..='z' | '0'..='9' | '`' | '-' | '=' | '[' | ']' | '\\' | ';' | '\'' | ',' | '.' | '/' => Some(c),
+        'A'..='Z' => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+/// TypeText展开后的一段输入
+#[derive(Debug, PartialEq)]
+enum TextPiece {
+    /// 按Unicode字符直接输入
+    Char(char),
+    /// 花括号转义的按键
+    Key(Key),
+}
+
+/// 解析TypeText的转义: {按键名}发送对应的按键，{{和}}是花括号本身，无法识别的内容原样输入
+fn parse_text(text: &str) -> Vec<TextPiece> {
+    let mut pieces = Vec::new();
+    let mut chars = text.chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                pieces.push(TextPiece::Char('{'));
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                pieces.push(TextPiece::Char('}'));
+            },
+            '{' => {
+                let name: String = chars.clone().take_while(|&c| c != '}').collect();
+                let closed = chars.clone().nth(name.chars().count()) == Some('}');
+                match serde_json::from_value::<Key>(serde_json::Value::String(name.clone())) {
+                    Ok(key) if closed => {
+                        // 跳过按键名和右花括号
+                        chars.nth(name.chars().count());
+                        pieces.push(TextPiece::Key(key));
+                    },
+                    _ => {
+                        warn!("无法识别的转义按键: {{{}", name);
+                        pieces.push(TextPiece::Char('{'));
+                    }
+                }
+            },
+            c => pieces.push(TextPiece::Char(c)),
+        }
+    }
+    
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// 把上次补充令牌的时间往前推，相当于过去了ms毫秒
+    fn elapse(limiter: &mut RateLimiter, ms: u64) {
+        limiter.last_refill -= Duration::from_millis(ms);
+    }
+    
+    #[test]
+    fn rate_limiter_allows_one_second_burst() {
+        let mut limiter = RateLimiter::new(10);
+        for _ in 0..10 {
+            assert_eq!(limiter.try_acquire(), None);
+        }
+        
+        let wait = limiter.try_acquire().expect("令牌用完后需要等待");
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(100));
+    }
+    
+    #[test]
+    fn rate_limiter_refills_at_fixed_rate() {
+        let mut limiter = RateLimiter::new(10);
+        for _ in 0..10 {
+            limiter.try_acquire();
+        }
+        
+        elapse(&mut limiter, 200);
+        assert_eq!(limiter.try_acquire(), None);
+        assert_eq!(limiter.try_acquire(), None);
+        assert!(limiter.try_acquire().is_some());
+    }
+    
+    #[test]
+    fn rate_limiter_caps_idle_refill() {
+        let mut limiter = RateLimiter::new(5);
+        elapse(&mut limiter, 10_000);
+        for _ in 0..5 {
+            assert_eq!(limiter.try_acquire(), None);
+        }
+        assert!(limiter.try_acquire().is_some());
+    }
+    
+    #[test]
+    fn rate_limiter_treats_zero_as_one_per_second() {
+        let mut limiter = RateLimiter::new(0);
+        assert_eq!(limiter.try_acquire(), None);
+        
+        let wait = limiter.try_acquire().expect("每秒只有一个令牌");
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+    
+    fn chars(text: &str) -> Vec<TextPiece> {
+        text.chars().map(TextPiece::Char).collect()
+    }
+    
+    #[test]
+    fn parse_text_doubled_braces_are_literal() {
+        assert_eq!(parse_text("{{gg}}"), chars("{gg}"));
+    }
+    
+    #[test]
+    fn parse_text_reads_key_escapes() {
+        assert_eq!(parse_text("gg{Enter}"), vec![
+            TextPiece::Char('g'),
+            TextPiece::Char('g'),
+            TextPiece::Key(Key::Enter),
+        ]);
+        assert_eq!(parse_text("{F1}{Escape}"), vec![TextPiece::Key(Key::F1), TextPiece::Key(Key::Escape)]);
+    }
+    
+    #[test]
+    fn parse_text_types_unknown_or_unclosed_escapes() {
+        assert_eq!(parse_text("{Nope}"), chars("{Nope}"));
+        assert_eq!(parse_text("a{Enter"), chars("a{Enter"));
+        assert_eq!(parse_text("a{"), chars("a{"));
+        assert_eq!(parse_text("a}"), chars("a}"));
+    }
+    
+    #[test]
+    fn parse_text_types_trailing_backslash() {
+        // 反斜杠不是转义符，结尾的反斜杠原样输入
+        assert_eq!(parse_text("ok\\"), chars("ok\\"));
+        assert_eq!(unshifted_key('\\'), Some('\\'));
+    }
+    
+    #[test]
+    fn parse_text_keeps_non_ascii() {
+        assert_eq!(parse_text("打野来中路{Enter}"), vec![
+            TextPiece::Char('打'),
+            TextPiece::Char('野'),
+            TextPiece::Char('来'),
+            TextPiece::Char('中'),
+            TextPiece::Char('路'),
+            TextPiece::Key(Key::Enter),
+        ]);
+        assert_eq!(unshifted_key('打'), None);
+        assert_eq!(unshifted_key('é'), None);
+    }
+    
+    #[test]
+    fn shifted_punctuation_is_typed_as_text() {
+        for c in ['!', '?', ':', '"', '{', '}', '+', '_', '~'] {
+            assert_eq!(unshifted_key(c), None, "{:?}", c);
+        }
+        assert_eq!(parse_text("gg!?"), chars("gg!?"));
+    }
+    
+    #[test]
+    fn unshifted_key_maps_letters_and_plain_punctuation() {
+        assert_eq!(unshifted_key('A'), Some('a'));
+        assert_eq!(unshifted_key('q'), Some('q'));
+        assert_eq!(unshifted_key('7'), Some('7'));
+        for c in ['`', '-', '=', '[', ']', ';', '\'', ',', '.', '/'] {
+            assert_eq!(unshifted_key(c), Some(c));
         }
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file
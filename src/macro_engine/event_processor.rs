@@ -1,26 +1,112 @@
 use crate::config::hero::Key;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeyState {
+    pub is_down: bool,
+    /// 最近一次按下的时间
+    pub pressed_at: Option<Instant>,
+}
 
 pub struct KeyEventProcessor {
-    key_states: HashMap<Key, bool>,
+    key_states: HashMap<Key, KeyState>,
+    /// 代替用户按住的按键，如双功能键按住时的修饰键；模拟的按键不经过钩子，需要单独记录
+    virtual_keys: Vec<Key>,
 }
 
 impl KeyEventProcessor {
     pub fn new() -> Self {
         Self {
             key_states: HashMap::new(),
+            virtual_keys: Vec::new(),
+        }
+    }
+    
+    /// 记录一次按下，返回这是否是按住不放时系统产生的自动重复
+    pub fn press(&mut self, key: &Key, now: Instant) -> bool {
+        let state = self.key_states.entry(key.clone()).or_insert(KeyState {
+            is_down: false,
+            pressed_at: None,
+        });
+        
+        if state.is_down {
+            return true;
         }
+        
+        state.is_down = true;
+        state.pressed_at = Some(now);
+        false
+    }
+    
+    /// 记录一次松开，返回这次按住的时长
+    pub fn release(&mut self, key: &Key, now: Instant) -> Option<Duration> {
+        let state = self.key_states.get_mut(key)?;
+        let held_for = match (state.is_down, state.pressed_at) {
+            (true, Some(pressed_at)) => Some(now.saturating_duration_since(pressed_at)),
+            _ => None,
+        };
+        
+        state.is_down = false;
+        held_for
     }
     
-    pub fn set_key_state(&mut self, key: Key, is_down: bool) {
-        self.key_states.insert(key, is_down);
+    /// 记录一个代替用户按下的按键
+    pub fn press_virtual(&mut self, key: &Key) {
+        self.virtual_keys.push(key.clone());
     }
     
+    /// 松开一个代替用户按下的按键，多次按下的要松开同样多次
+    pub fn release_virtual(&mut self, key: &Key) {
+        if let Some(index) = self.virtual_keys.iter().position(|pressed| pressed == key) {
+            self.virtual_keys.remove(index);
+        }
+    }
+    
+    /// 通用修饰键按住任意一侧都算按住，代替用户按下的按键同样算按住
     pub fn is_key_down(&self, key: &Key) -> bool {
-        *self.key_states.get(key).unwrap_or(&false)
+        let is_down = |key: &Key| {
+            self.virtual_keys.contains(key) || self.key_states.get(key).is_some_and(|state| state.is_down)
+        };
+        is_down(key) || key.modifier_sides().is_some_and(|sides| sides.iter().any(is_down))
     }
     
-    pub fn are_keys_down(&self, keys: &[Key]) -> bool {
-        keys.iter().all(|key| self.is_key_down(key))
+    /// 按键已经按住的时长，没有按住时返回None
+    pub fn held_for(&self, key: &Key, now: Instant) -> Option<Duration> {
+        match self.key_states.get(key) {
+            Some(KeyState { is_down: true, pressed_at: Some(pressed_at), .. }) => {
+                Some(now.saturating_duration_since(*pressed_at))
+            }
+            _ => None,
+        }
+    }
+    
+    /// 按实际的物理状态校正keys和所有记录过的按键，返回状态发生变化的按键
+    pub fn resync(&mut self, keys: &[Key], is_physically_down: &dyn Fn(&Key) -> bool, now: Instant) -> Vec<Key> {
+        // 还没有记录过的按键先当作松开，实际按住的在下面校正
+        for key in keys {
+            self.key_states.entry(key.clone()).or_insert(KeyState {
+                is_down: false,
+                pressed_at: None,
+            });
+        }
+        
+        let mut changed = Vec::new();
+        
+        for (key, state) in self.key_states.iter_mut() {
+            let is_down = is_physically_down(key);
+            if is_down == state.is_down {
+                continue;
+            }
+            
+            // 错过了按下时无法知道真正的按下时间，只能从现在开始计算
+            if is_down {
+                state.pressed_at = Some(now);
+            }
+            state.is_down = is_down;
+            changed.push(key.clone());
+        }
+        
+        changed
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file
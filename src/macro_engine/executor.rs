@@ -0,0 +1,113 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+use log::{debug, error, info};
+
+use crate::config::hero::{ActionTiming, BusyPolicy, KeyAction};
+use crate::keyboard::simulator::ActionOutput;
+
+/// 交给执行线程的一组动作
+pub struct ComboJob {
+    pub name: String,
+    pub actions: Vec<KeyAction>,
+    /// 执行动作使用的按键时间参数
+    pub timing: ActionTiming,
+    /// 提交时已有连招在执行的处理方式
+    pub policy: BusyPolicy,
+}
+
+struct QueuedJob {
+    job: ComboJob,
+    generation: u64,
+}
+
+/// 执行线程和提交方共享的状态
+struct ExecutorState {
+    /// 每次取消时加一，更早一代的任务不再执行
+    generation: AtomicU64,
+    /// 已提交但还没有执行完的任务数
+    outstanding: AtomicUsize,
+}
+
+/// 在独立线程上依次执行连招动作，钩子回调只负责提交任务
+pub struct ComboExecutor {
+    sender: Sender<QueuedJob>,
+    state: Arc<ExecutorState>,
+}
+
+impl ComboExecutor {
+    /// 所有连招都在output上依次执行
+    pub fn start(mut output: Box<dyn ActionOutput>) -> Self {
+        let (sender, receiver) = channel::<QueuedJob>();
+        let state = Arc::new(ExecutorState {
+            generation: AtomicU64::new(0),
+            outstanding: AtomicUsize::new(0),
+        });
+        let worker_state = state.clone();
+        
+        let spawned = thread::Builder::new()
+            .name("combo-executor".to_string())
+            .spawn(move || {
+                let state = worker_state;
+                
+                for queued in receiver {
+                    let generation = queued.generation;
+                    let job = queued.job;
+                    
+                    if state.generation.load(Ordering::SeqCst) != generation {
+                        debug!("跳过已取消的连招: {}", job.name);
+                    } else {
+                        info!("执行连招: {}", job.name);
+                        let cancelled = || state.generation.load(Ordering::SeqCst) != generation;
+                        if !output.execute_actions_until(&job.actions, job.timing, &cancelled) {
+                            info!("连招 {} 已被取消", job.name);
+                            output.release_held_keys();
+                        }
+                    }
+                    
+                    state.outstanding.fetch_sub(1, Ordering::SeqCst);
+                }
+                
+                debug!("连招执行线程退出");
+            });
+        
+        if let Err(e) = spawned {
+            error!("无法启动连招执行线程: {}", e);
+        }
+        
+        Self {
+            sender,
+            state,
+        }
+    }
+    
+    pub fn submit(&self, job: ComboJob) {
+        let busy = self.state.outstanding.load(Ordering::SeqCst) > 0;
+        
+        match job.policy {
+            BusyPolicy::Drop if busy => {
+                info!("已有连招在执行，丢弃连招: {}", job.name);
+                return;
+            }
+            BusyPolicy::Preempt if busy => {
+                info!("连招 {} 打断正在执行的连招", job.name);
+                self.cancel();
+            }
+            _ => {}
+        }
+        
+        debug!("提交连招任务: {}", job.name);
+        let generation = self.state.generation.load(Ordering::SeqCst);
+        self.state.outstanding.fetch_add(1, Ordering::SeqCst);
+        if self.sender.send(QueuedJob { job, generation }).is_err() {
+            self.state.outstanding.fetch_sub(1, Ordering::SeqCst);
+            error!("连招执行线程已退出，任务被丢弃");
+        }
+    }
+    
+    /// 取消正在执行和排队中的所有连招
+    pub fn cancel(&self) {
+        self.state.generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
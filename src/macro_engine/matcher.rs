@@ -0,0 +1,928 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::{debug, warn};
+
+use crate::config::hero::{HeroConfig, ComboTrigger, ComboAction, ActionTiming, DualRoleKey, Key, PrefixPolicy, SequenceOrder, Step};
+
+/// 无序触发条件展开为排列时允许的最大按键数
+const MAX_PERMUTATION_KEYS: usize = 5;
+
+/// 一个连招最多展开的按键路径数
+const MAX_PATHS: usize = 256;
+
+/// 编译后的单个连招
+pub struct CompiledCombo {
+    pub name: String,
+    pub trigger: ComboTrigger,
+    pub action: ComboAction,
+    /// 合并英雄默认值后的按键时间参数
+    pub timing: ActionTiming,
+}
+
+impl CompiledCombo {
+    /// 检查一条走到终点的部分匹配是否满足此连招的时间和顺序要求
+    fn accepts(&self, state: &PartialMatch) -> bool {
+        let first = state.times[0];
+        let last = *state.times.last().unwrap();
+        
+        if let Some(window) = self.window() {
+            if last.duration_since(first).as_millis() > window as u128 {
+                return false;
+            }
+        }
+        
+        match &self.trigger.order {
+            SequenceOrder::Strict { allow_other_keys, .. } => {
+                // 跳过的按键只有在此连招自己的忽略集合里才不算夹杂
+                let interleaved = state.interleaved
+                    || state.skipped.iter().any(|key| !self.trigger.ignored_keys.contains(key));
+                if interleaved && !allow_other_keys {
+                    return false;
+                }
+                
+                (1..state.times.len()).all(|i| match self.trigger.order.max_gap(i) {
+                    Some(gap) => state.times[i].duration_since(state.times[i - 1]).as_millis() <= gap as u128,
+                    None => true,
+                })
+            }
+            SequenceOrder::Any | SequenceOrder::Chord { .. } => true,
+        }
+    }
+    
+    /// 整个序列必须完成的时间窗口，和弦不超过它的容差
+    fn window(&self) -> Option<u64> {
+        match self.trigger.order {
+            SequenceOrder::Chord { tolerance_ms, .. } => {
+                Some(self.trigger.time_window.map_or(tolerance_ms, |window| window.min(tolerance_ms)))
+            }
+            _ => self.trigger.time_window,
+        }
+    }
+    
+    /// 暂扣序列前面按键的最长等待时间，需要扣下按键的和弦至少等待它的容差
+    fn hold_timeout(&self) -> Option<u64> {
+        match self.trigger.order {
+            SequenceOrder::Chord { tolerance_ms, suppress: true } => {
+                Some(self.trigger.hold_timeout.unwrap_or(0).max(tolerance_ms))
+            }
+            _ => self.trigger.hold_timeout,
+        }
+    }
+    
+    /// 展开后插入前缀树的按键路径
+    fn paths(&self) -> Vec<Vec<Key>> {
+        let expanded = match self.trigger.order {
+            SequenceOrder::Strict { .. } => expand_steps(&self.trigger.sequence),
+            SequenceOrder::Any | SequenceOrder::Chord { .. } => {
+                // 无序触发条件只关心按键是否出现过，重复的步骤只算一次；Repeat要求的多次按下保留
+                let mut steps: Vec<Step> = Vec::new();
+                for step in &self.trigger.sequence {
+                    if !steps.contains(step) {
+                        steps.push(step.clone());
+                    }
+                }
+                
+                let mut expanded = Vec::new();
+                for keys in expand_steps(&steps) {
+                    if keys.len() > MAX_PERMUTATION_KEYS {
+                        warn!("连招 {} 的无序触发键过多，只按配置顺序匹配", self.name);
+                        expanded.push(keys);
+                    } else {
+                        expanded.extend(permutations(&keys));
+                    }
+                }
+                expanded
+            }
+        };
+        
+        let mut paths: Vec<Vec<Key>> = Vec::new();
+        for path in expanded {
+            if !path.is_empty() && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        
+        if paths.len() > MAX_PATHS {
+            warn!("连招 {} 展开后的按键路径过多，只使用前 {} 条", self.name, MAX_PATHS);
+            paths.truncate(MAX_PATHS);
+        }
+        paths
+    }
+    
+    /// 步骤之间是否允许夹杂其他按键
+    fn allows_other_keys(&self) -> bool {
+        match self.trigger.order {
+            SequenceOrder::Strict { allow_other_keys, .. } => allow_other_keys,
+            // 无序触发条件一直允许夹杂其他按键
+            SequenceOrder::Any | SequenceOrder::Chord { .. } => true,
+        }
+    }
+}
+
+/// 把步骤展开为具体的按键序列: 任选的步骤每个候选展开一条，重复的步骤展开为多个相同的按键
+fn expand_steps(steps: &[Step]) -> Vec<Vec<Key>> {
+    let mut paths: Vec<Vec<Key>> = vec![Vec::new()];
+    
+    for step in steps {
+        paths = match step {
+            Step::Key(key) => paths.into_iter()
+                .map(|mut path| {
+                    path.push(key.clone());
+                    path
+                })
+                .collect(),
+            Step::AnyOf { any_of } => paths.iter()
+                .flat_map(|path| any_of.iter().map(move |key| {
+                    let mut path = path.clone();
+                    path.push(key.clone());
+                    path
+                }))
+                .collect(),
+            Step::Repeat { key, times } => paths.into_iter()
+                .map(|mut path| {
+                    path.extend(std::iter::repeat(key.clone()).take(*times));
+                    path
+                })
+                .collect(),
+        };
+    }
+    
+    paths
+}
+
+fn permutations(keys: &[Key]) -> Vec<Vec<Key>> {
+    if keys.len() <= 1 {
+        return vec![keys.to_vec()];
+    }
+    
+    let mut result = Vec::new();
+    for i in 0..keys.len() {
+        let mut rest = keys.to_vec();
+        let first = rest.remove(i);
+        for mut tail in permutations(&rest) {
+            tail.insert(0, first.clone());
+            result.push(tail);
+        }
+    }
+    result
+}
+
+/// 合并两个时间限制，取更宽松的一个 (None表示不限制)
+fn looser(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => None,
+    }
+}
+
+struct TrieNode {
+    parent: usize,
+    children: HashMap<Key, usize>,
+    /// 在此节点完成的连招
+    terminals: Vec<usize>,
+    /// 经过此节点的连招中最宽松的整体时间窗口
+    max_window: Option<u64>,
+    /// 进入子节点时最宽松的单步间隔
+    max_next_gap: Option<u64>,
+    /// 经过此节点的连招中是否有允许夹杂其他按键的
+    allows_other_keys: bool,
+    /// 停在此节点时暂扣按键的最长等待时间
+    hold_timeout: Option<u64>,
+    /// 经过此节点的连招忽略的按键
+    ignored_keys: HashSet<Key>,
+}
+
+impl TrieNode {
+    fn new(parent: usize) -> Self {
+        Self {
+            parent,
+            children: HashMap::new(),
+            terminals: Vec::new(),
+            max_window: Some(0),
+            max_next_gap: Some(0),
+            allows_other_keys: false,
+            hold_timeout: None,
+            ignored_keys: HashSet::new(),
+        }
+    }
+}
+
+/// 从英雄配置编译出的连招前缀树，编译后不再改变
+pub struct ComboTrie {
+    nodes: Vec<TrieNode>,
+    combos: Vec<CompiledCombo>,
+    /// 需要所有按键同时按住的连招，按其中的按键索引
+    held_combos: HashMap<Key, Vec<usize>>,
+    prefix_policy: PrefixPolicy,
+    reset_keys: Vec<Key>,
+    dual_role_keys: Vec<DualRoleKey>,
+    /// 英雄忽略的按键，不参与任何匹配
+    ignored_keys: HashSet<Key>,
+}
+
+/// 一次按键完成的连招
+#[derive(Clone)]
+pub struct ComboMatch {
+    pub id: usize,
+    /// 匹配的步数
+    pub length: usize,
+    /// 完成时所在的前缀树节点，同时按住的连招没有节点
+    node: Option<usize>,
+    /// 还有以此为前缀的更长连招时，最晚需要在此时间前继续
+    pub extendable_until: Option<Instant>,
+    /// 匹配用到的按键的按下时间
+    pub times: Vec<Instant>,
+}
+
+/// 按键推进后可以暂扣按键的位置
+pub struct HoldPoint {
+    node: usize,
+    pub timeout: u64,
+}
+
+/// 推进一个按键的结果
+pub struct Advance {
+    pub matches: Vec<ComboMatch>,
+    /// 按键推进到了需要暂扣按键的连招中间
+    pub hold: Option<HoldPoint>,
+    /// 跳过或夹杂了按键、仍停在需要暂扣按键位置上的部分匹配
+    pub kept: Vec<HoldPoint>,
+}
+
+impl ComboMatch {
+    /// 同时按住完成的连招
+    pub fn held(id: usize, length: usize, now: Instant) -> Self {
+        Self {
+            id,
+            length,
+            node: None,
+            extendable_until: None,
+            times: vec![now],
+        }
+    }
+}
+
+impl ComboTrie {
+    pub fn compile(config: &HeroConfig) -> Self {
+        let mut trie = Self {
+            nodes: vec![TrieNode::new(0)],
+            combos: Vec::new(),
+            held_combos: HashMap::new(),
+            prefix_policy: config.prefix_policy,
+            reset_keys: config.reset_keys.clone(),
+            dual_role_keys: config.dual_role_keys.clone(),
+            ignored_keys: config.ignored_keys.iter().cloned().collect(),
+        };
+        
+        // 从配置文件加载时已经检查过，这里只提醒代码里定义的英雄
+        if let Err(e) = config.check_calls() {
+            warn!("英雄 {} 的连招调用有误: {}", config.name, e);
+        }
+        
+        // 按名称排序编译，保证连招编号与HashMap的遍历顺序无关
+        let mut combos: Vec<_> = config.combos.iter().collect();
+        combos.sort_by(|a, b| a.0.cmp(b.0));
+        
+        for (name, (trigger, action)) in combos {
+            if trigger.sequence.is_empty() {
+                if config.is_called(name) {
+                    debug!("连招 {} 没有触发按键，只供其他连招调用", name);
+                } else {
+                    warn!("连招 {} 没有触发按键，已忽略", name);
+                }
+                continue;
+            }
+            
+            if let Some(key) = trigger.sequence.iter().flat_map(|step| step.keys()).find(|key| trie.ignored_keys.contains(*key)) {
+                warn!("按键 {:?} 被英雄忽略，连招 {} 不会匹配到它", key, name);
+            }
+            
+            // 调用的连招在编译时展开，执行时不需要再查找
+            let mut action = action.clone();
+            action.keys = config.expand_calls(&action.keys);
+            
+            let id = trie.combos.len();
+            trie.combos.push(CompiledCombo {
+                name: name.clone(),
+                trigger: trigger.clone(),
+                timing: action.timing(config.timing),
+                action,
+            });
+            
+            // 没有时间窗口的无序连招要求所有按键同时按住
+            if trigger.time_window.is_none() && trigger.order == SequenceOrder::Any {
+                for key in trigger.sequence.iter().flat_map(|step| step.keys()) {
+                    let combos = trie.held_combos.entry(key.clone()).or_default();
+                    if !combos.contains(&id) {
+                        combos.push(id);
+                    }
+                }
+                continue;
+            }
+            
+            for path in trie.combos[id].paths() {
+                trie.insert(id, &path);
+            }
+        }
+        
+        trie.warn_conflicts();
+        debug!("英雄 [{}] 的连招前缀树: {} 个节点, {} 个连招", config.name, trie.nodes.len(), trie.combos.len());
+        trie
+    }
+    
+    fn insert(&mut self, id: usize, path: &[Key]) {
+        let window = self.combos[id].window();
+        let allows_other_keys = self.combos[id].allows_other_keys();
+        let hold_timeout = self.combos[id].hold_timeout();
+        let mut node = 0;
+        
+        for (step, key) in path.iter().enumerate() {
+            let gap = self.combos[id].trigger.order.max_gap(step);
+            self.nodes[node].max_next_gap = looser(self.nodes[node].max_next_gap, gap);
+            
+            node = match self.nodes[node].children.get(key) {
+                Some(&child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TrieNode::new(node));
+                    self.nodes[node].children.insert(key.clone(), child);
+                    child
+                }
+            };
+            
+            let current = &mut self.nodes[node];
+            current.max_window = looser(current.max_window, window);
+            current.allows_other_keys |= allows_other_keys;
+            current.ignored_keys.extend(self.combos[id].trigger.ignored_keys.iter().cloned());
+            
+            // 最后一步完成连招，只有中间的步骤需要暂扣
+            if step + 1 < path.len() {
+                if let Some(timeout) = hold_timeout {
+                    current.hold_timeout = Some(current.hold_timeout.unwrap_or(0).max(timeout));
+                }
+            }
+        }
+        
+        self.nodes[node].terminals.push(id);
+    }
+    
+    /// 同一节点上优先级相同的连招只能靠名称决定，提醒配置者
+    fn warn_conflicts(&self) {
+        for node in &self.nodes {
+            for (i, &a) in node.terminals.iter().enumerate() {
+                for &b in &node.terminals[i + 1..] {
+                    let (first, second) = (&self.combos[a].trigger, &self.combos[b].trigger);
+                    if a != b && first.kind == second.kind && first.layer == second.layer
+                        && first.modifiers == second.modifiers && first.priority == second.priority {
+                        warn!("连招 {} 与 {} 触发条件相同且优先级相同，将按名称顺序选择",
+                              self.combos[a].name, self.combos[b].name);
+                    }
+                }
+            }
+        }
+    }
+    
+    pub fn is_reset_key(&self, key: &Key) -> bool {
+        self.reset_keys.contains(key)
+    }
+    
+    pub fn is_ignored(&self, key: &Key) -> bool {
+        self.ignored_keys.contains(key)
+    }
+    
+    pub fn dual_role(&self, key: &Key) -> Option<&DualRoleKey> {
+        self.dual_role_keys.iter().find(|dual| &dual.key == key)
+    }
+    
+    pub fn prefix_policy(&self) -> PrefixPolicy {
+        self.prefix_policy
+    }
+    
+    /// 从同时完成的连招中选出要触发的一个: 步数最多优先，其次要求的修饰键多的，再次优先级，最后按名称
+    pub fn resolve<'a>(&self, matches: &'a [ComboMatch]) -> Option<&'a ComboMatch> {
+        matches.iter().min_by_key(|m| {
+            let trigger = &self.combos[m.id].trigger;
+            (Reverse(m.length), Reverse(trigger.modifiers.required.len()), Reverse(trigger.priority), self.combos[m.id].name.as_str())
+        })
+    }
+    
+    /// 从node按key能到达的子节点，左右两侧的修饰键同样能走通用修饰键的分支
+    fn children_for<'a>(&'a self, node: usize, key: &Key) -> impl Iterator<Item = usize> + 'a {
+        let children = &self.nodes[node].children;
+        Some(key.clone()).into_iter()
+            .chain(key.generic_modifier())
+            .filter_map(move |key| children.get(&key).copied())
+    }
+    
+    /// descendant是否是ancestor本身或其后代
+    fn is_within(&self, mut descendant: usize, ancestor: usize) -> bool {
+        loop {
+            if descendant == ancestor {
+                return true;
+            }
+            if descendant == 0 {
+                return false;
+            }
+            descendant = self.nodes[descendant].parent;
+        }
+    }
+    
+    /// match是否是从pending所在节点继续匹配得到的更长连招
+    pub fn extends(&self, longer: &ComboMatch, shorter: &ComboMatch) -> bool {
+        match (longer.node, shorter.node) {
+            (Some(l), Some(s)) => l != s && self.is_within(l, s),
+            _ => false,
+        }
+    }
+    
+    /// 连招是否经过了之前的暂扣位置
+    pub fn completes_hold(&self, matched: &ComboMatch, hold: &HoldPoint) -> bool {
+        match matched.node {
+            Some(node) => node != hold.node && self.is_within(node, hold.node),
+            None => false,
+        }
+    }
+    
+    /// 新的暂扣位置是否是在之前的暂扣位置上继续推进
+    pub fn continues_hold(&self, next: &HoldPoint, previous: &HoldPoint) -> bool {
+        next.node != previous.node && self.is_within(next.node, previous.node)
+    }
+    
+    /// 之前暂扣位置上的部分匹配是否跳过或夹杂了按键后仍然存活
+    pub fn keeps_hold(&self, kept: &[HoldPoint], previous: &HoldPoint) -> bool {
+        kept.iter().any(|point| point.node == previous.node)
+    }
+    
+    /// 停在此节点的部分匹配最晚可以继续到什么时候
+    fn extension_deadline(&self, state: &PartialMatch, stale_after: Duration) -> Option<Instant> {
+        let node = &self.nodes[state.node];
+        if node.children.is_empty() {
+            return None;
+        }
+        
+        let start = state.times[0];
+        let last = *state.times.last().unwrap();
+        let mut deadline = start + stale_after;
+        if let Some(window) = node.max_window {
+            deadline = deadline.min(start + Duration::from_millis(window));
+        }
+        if let Some(gap) = node.max_next_gap {
+            deadline = deadline.min(last + Duration::from_millis(gap));
+        }
+        Some(deadline)
+    }
+    
+    pub fn combo(&self, id: usize) -> &CompiledCombo {
+        &self.combos[id]
+    }
+    
+    /// 包含指定按键、需要同时按住的连招
+    pub fn held_combos(&self, key: &Key) -> Vec<usize> {
+        Some(key.clone()).into_iter()
+            .chain(key.generic_modifier())
+            .filter_map(|key| self.held_combos.get(&key))
+            .flatten()
+            .copied()
+            .collect()
+    }
+    
+    /// 部分匹配是否已经不可能再完成
+    fn is_stale(&self, state: &PartialMatch, now: Instant, stale_after: Duration) -> bool {
+        let node = &self.nodes[state.node];
+        let start = state.times[0];
+        let last = *state.times.last().unwrap();
+        
+        if node.children.is_empty() || now.duration_since(start) > stale_after {
+            return true;
+        }
+        if let Some(window) = node.max_window {
+            if now.duration_since(start).as_millis() > window as u128 {
+                return true;
+            }
+        }
+        if let Some(gap) = node.max_next_gap {
+            if now.duration_since(last).as_millis() > gap as u128 {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[derive(Clone)]
+struct PartialMatch {
+    node: usize,
+    /// 每一步的按下时间
+    times: Vec<Instant>,
+    /// 步骤之间是否夹杂了其他按键
+    interleaved: bool,
+    /// 因为在某些连招的忽略集合里而跳过的按键
+    skipped: Vec<Key>,
+}
+
+/// 在前缀树上逐键推进的匹配器，保存当前英雄的部分匹配
+pub struct ComboMatcher {
+    trie: Arc<ComboTrie>,
+    partials: Vec<PartialMatch>,
+    stale_after: Duration,
+}
+
+impl ComboMatcher {
+    pub fn new(trie: Arc<ComboTrie>, stale_after: Duration) -> Self {
+        Self {
+            trie,
+            partials: Vec::new(),
+            stale_after,
+        }
+    }
+    
+    /// 是否由指定的前缀树创建
+    pub fn is_compiled_from(&self, trie: &Arc<ComboTrie>) -> bool {
+        Arc::ptr_eq(&self.trie, trie)
+    }
+    
+    /// 推进一个按下的按键，返回因此完成的连招
+    pub fn advance(&mut self, key: &Key, now: Instant) -> Advance {
+        let trie = self.trie.clone();
+        let mut next: Vec<PartialMatch> = Vec::with_capacity(self.partials.len() + 1);
+        // 本次按键推进后到达的状态在next中的下标
+        let mut advanced = Vec::new();
+        // 跳过或夹杂本次按键后保留下来的状态在next中的下标
+        let mut survived = Vec::new();
+        
+        for mut state in self.partials.drain(..) {
+            if trie.is_stale(&state, now, self.stale_after) {
+                continue;
+            }
+            
+            for child in trie.children_for(state.node, key) {
+                let mut stepped = state.clone();
+                stepped.node = child;
+                stepped.times.push(now);
+                advanced.push(next.len());
+                next.push(stepped);
+            }
+            
+            // 当前按键也可以被跳过或作为夹杂的按键，保留原来的状态
+            let node = &trie.nodes[state.node];
+            if node.ignored_keys.contains(key) {
+                state.skipped.push(key.clone());
+                survived.push(next.len());
+                next.push(state);
+            } else if node.allows_other_keys {
+                state.interleaved = true;
+                survived.push(next.len());
+                next.push(state);
+            }
+        }
+        
+        for child in trie.children_for(0, key) {
+            advanced.push(next.len());
+            next.push(PartialMatch {
+                node: child,
+                times: vec![now],
+                interleaved: false,
+                skipped: Vec::new(),
+            });
+        }
+        
+        let mut completed: Vec<ComboMatch> = Vec::new();
+        for &index in &advanced {
+            let state = &next[index];
+            for &id in &trie.nodes[state.node].terminals {
+                if !completed.iter().any(|m| m.id == id) && trie.combos[id].accepts(state) {
+                    completed.push(ComboMatch {
+                        id,
+                        length: state.times.len(),
+                        node: Some(state.node),
+                        extendable_until: trie.extension_deadline(state, self.stale_after),
+                        times: state.times.clone(),
+                    });
+                }
+            }
+        }
+        
+        // 推进得最深的、需要暂扣按键的位置
+        let hold = advanced.iter()
+            .map(|&index| &next[index])
+            .filter(|state| !trie.nodes[state.node].children.is_empty())
+            .filter_map(|state| trie.nodes[state.node].hold_timeout.map(|timeout| (state.times.len(), state.node, timeout)))
+            .max()
+            .map(|(_, node, timeout)| HoldPoint { node, timeout });
+        let kept = survived.iter()
+            .map(|&index| &next[index])
+            .filter(|state| !trie.nodes[state.node].children.is_empty())
+            .filter_map(|state| trie.nodes[state.node].hold_timeout.map(|timeout| HoldPoint { node: state.node, timeout }))
+            .collect();
+        
+        // 同一节点只保留起点最晚的部分匹配，保证每次按键的开销不随连招数量增长
+        let mut best: HashMap<(usize, bool), usize> = HashMap::new();
+        for (index, state) in next.iter().enumerate() {
+            if trie.nodes[state.node].children.is_empty() {
+                continue;
+            }
+            let slot = best.entry((state.node, state.interleaved)).or_insert(index);
+            if next[*slot].times[0] < state.times[0] {
+                *slot = index;
+            }
+        }
+        let mut keep: Vec<usize> = best.into_values().collect();
+        keep.sort_unstable();
+        self.partials = keep.into_iter().map(|index| next[index].clone()).collect();
+        
+        Advance {
+            matches: completed,
+            hold,
+            kept,
+        }
+    }
+    
+    /// 按住不放产生的重复按下只用来完成允许重复的连招，不改变部分匹配
+    pub fn repeat(&self, key: &Key, now: Instant) -> Vec<ComboMatch> {
+        let trie = &self.trie;
+        let root = PartialMatch {
+            node: 0,
+            times: Vec::new(),
+            interleaved: false,
+            skipped: Vec::new(),
+        };
+        
+        let mut completed: Vec<ComboMatch> = Vec::new();
+        for state in self.partials.iter().filter(|s| !trie.is_stale(s, now, self.stale_after)).chain(Some(&root)) {
+            for child in trie.children_for(state.node, key) {
+                let mut stepped = state.clone();
+                stepped.node = child;
+                stepped.times.push(now);
+                for &id in &trie.nodes[child].terminals {
+                    let combo = &trie.combos[id];
+                    if combo.trigger.allow_repeat && !completed.iter().any(|m| m.id == id) && combo.accepts(&stepped) {
+                        completed.push(ComboMatch {
+                            id,
+                            length: stepped.times.len(),
+                            node: Some(child),
+                            extendable_until: None,
+                            times: stepped.times.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        completed
+    }
+    
+    /// 是否还有停在指定连招节点或其后的部分匹配，即更长的连招仍可能完成
+    pub fn can_extend(&self, shorter: &ComboMatch) -> bool {
+        match shorter.node {
+            Some(node) => self.partials.iter().any(|state| self.trie.is_within(state.node, node)),
+            None => false,
+        }
+    }
+    
+    /// 消耗一次匹配用到的按键: 包含这些按键的部分匹配全部作废，
+    /// 但以这次匹配为前缀、还在继续的更长连招保留
+    pub fn consume(&mut self, matched: &ComboMatch) {
+        let trie = &self.trie;
+        self.partials.retain(|state| {
+            let extends_match = matched.node.is_some_and(|node| trie.is_within(state.node, node));
+            extends_match || !state.times.iter().any(|time| matched.times.contains(time))
+        });
+    }
+    
+    /// 清除所有部分匹配
+    pub fn reset(&mut self) {
+        self.partials.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::hero::KeyAction;
+    
+    fn key(c: char) -> Key {
+        Key::Character(c)
+    }
+    
+    /// 添加一个严格按顺序、带时间窗口的连招
+    fn strict<'a>(config: &'a mut HeroConfig, name: &str, keys: &str, window: u64) -> &'a mut ComboTrigger {
+        let trigger = config.add_combo(name, keys.chars().map(key).collect(), vec![KeyAction::Press(key('x'))], Some(window), true, HashSet::new());
+        trigger.order = SequenceOrder::Strict { max_gaps: Vec::new(), allow_other_keys: false };
+        trigger
+    }
+    
+    fn matcher(config: &HeroConfig) -> ComboMatcher {
+        ComboMatcher::new(Arc::new(ComboTrie::compile(config)), Duration::from_secs(2))
+    }
+    
+    fn ms(start: Instant, offset: u64) -> Instant {
+        start + Duration::from_millis(offset)
+    }
+    
+    fn names(matcher: &ComboMatcher, matches: &[ComboMatch]) -> Vec<String> {
+        let mut names: Vec<String> = matches.iter().map(|m| matcher.trie.combo(m.id).name.clone()).collect();
+        names.sort();
+        names
+    }
+    
+    #[test]
+    fn advance_completes_sequence_in_order() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "eq", "eq", 300);
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        assert!(matcher.advance(&key('e'), start).matches.is_empty());
+        let advance = matcher.advance(&key('q'), ms(start, 50));
+        assert_eq!(names(&matcher, &advance.matches), vec!["eq"]);
+        assert_eq!(advance.matches[0].length, 2);
+        assert_eq!(advance.matches[0].times, vec![start, ms(start, 50)]);
+    }
+    
+    #[test]
+    fn advance_rejects_wrong_order_and_other_keys() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "eq", "eq", 300);
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('q'), start);
+        assert!(matcher.advance(&key('e'), ms(start, 10)).matches.is_empty());
+        
+        // 不允许夹杂时，中间的其他按键让部分匹配作废
+        matcher.advance(&key('z'), ms(start, 20));
+        assert!(matcher.advance(&key('q'), ms(start, 30)).matches.is_empty());
+    }
+    
+    #[test]
+    fn advance_respects_window_and_gaps() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "eq", "eq", 100);
+        strict(&mut config, "wr", "wr", 1000).order = SequenceOrder::Strict {
+            max_gaps: vec![None, Some(50)],
+            allow_other_keys: false,
+        };
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('e'), start);
+        assert!(matcher.advance(&key('q'), ms(start, 150)).matches.is_empty());
+        
+        matcher.advance(&key('w'), ms(start, 200));
+        assert!(matcher.advance(&key('r'), ms(start, 280)).matches.is_empty());
+        
+        matcher.advance(&key('w'), ms(start, 300));
+        let advance = matcher.advance(&key('r'), ms(start, 340));
+        assert_eq!(names(&matcher, &advance.matches), vec!["wr"]);
+    }
+    
+    #[test]
+    fn advance_prunes_stale_partials() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "eqw", "eqw", 100);
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('e'), start);
+        matcher.advance(&key('q'), ms(start, 20));
+        assert_eq!(matcher.partials.len(), 1);
+        
+        // 超过时间窗口的部分匹配在下一次推进时先被清除，不会再完成连招
+        assert!(matcher.advance(&key('w'), ms(start, 200)).matches.is_empty());
+        assert!(matcher.partials.is_empty());
+    }
+    
+    #[test]
+    fn advance_keeps_latest_partial_per_node() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "ab", "ab", 1000).order = SequenceOrder::Strict {
+            max_gaps: Vec::new(),
+            allow_other_keys: true,
+        };
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('a'), start);
+        matcher.advance(&key('a'), ms(start, 10));
+        assert_eq!(matcher.partials.len(), 2);
+        
+        // 两个部分匹配都夹杂了按键且停在同一节点，只保留起点最晚的
+        matcher.advance(&key('z'), ms(start, 20));
+        assert_eq!(matcher.partials.len(), 1);
+        assert_eq!(matcher.partials[0].times[0], ms(start, 10));
+    }
+    
+    #[test]
+    fn consume_drops_partials_sharing_keys_but_keeps_extensions() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "qe", "qe", 500);
+        strict(&mut config, "ew", "ew", 500);
+        strict(&mut config, "qew", "qew", 500);
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('q'), start);
+        let advance = matcher.advance(&key('e'), ms(start, 10));
+        assert_eq!(names(&matcher, &advance.matches), vec!["qe"]);
+        
+        matcher.consume(&advance.matches[0]);
+        let advance = matcher.advance(&key('w'), ms(start, 20));
+        assert_eq!(names(&matcher, &advance.matches), vec!["qew"]);
+    }
+    
+    #[test]
+    fn resolve_prefers_longer_then_priority_then_name() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "b", "b", 500);
+        strict(&mut config, "ab", "ab", 500);
+        strict(&mut config, "cd_low", "cd", 500);
+        strict(&mut config, "cd_high", "cd", 500).priority = 5;
+        strict(&mut config, "fg_first", "fg", 500);
+        strict(&mut config, "fg_second", "fg", 500);
+        let mut matcher = matcher(&config);
+        let trie = matcher.trie.clone();
+        let start = Instant::now();
+        
+        let mut winner = |first: char, second: char| {
+            matcher.advance(&key(first), start);
+            let advance = matcher.advance(&key(second), ms(start, 10));
+            matcher.reset();
+            trie.resolve(&advance.matches).map(|m| trie.combo(m.id).name.clone())
+        };
+        
+        assert_eq!(winner('a', 'b').as_deref(), Some("ab"));
+        assert_eq!(winner('c', 'd').as_deref(), Some("cd_high"));
+        assert_eq!(winner('f', 'g').as_deref(), Some("fg_first"));
+        assert!(trie.resolve(&[]).is_none());
+    }
+    
+    #[test]
+    fn hold_points_follow_the_sequence() {
+        let mut config = HeroConfig::new("test");
+        let trigger = strict(&mut config, "eqw", "eqw", 1000);
+        trigger.hold_timeout = Some(200);
+        trigger.ignored_keys = vec![key('z')];
+        let mut matcher = matcher(&config);
+        let trie = matcher.trie.clone();
+        let start = Instant::now();
+        
+        let first = matcher.advance(&key('e'), start).hold.unwrap();
+        assert_eq!(first.timeout, 200);
+        
+        // 被忽略的按键不推进序列，原来的暂扣位置仍然存活
+        let skipped = matcher.advance(&key('z'), ms(start, 10));
+        assert!(skipped.hold.is_none());
+        assert!(trie.keeps_hold(&skipped.kept, &first));
+        
+        let second = matcher.advance(&key('q'), ms(start, 20)).hold.unwrap();
+        assert!(trie.continues_hold(&second, &first));
+        
+        let advance = matcher.advance(&key('w'), ms(start, 30));
+        assert!(advance.hold.is_none());
+        assert!(trie.completes_hold(&advance.matches[0], &second));
+    }
+    
+    /// 添加一个Q、W的和弦
+    fn chord(config: &mut HeroConfig, window: u64, tolerance_ms: u64) {
+        strict(config, "qw", "qw", window).order = SequenceOrder::Chord { tolerance_ms, suppress: false };
+    }
+    
+    #[test]
+    fn chord_matches_within_tolerance_in_any_order() {
+        let mut config = HeroConfig::new("test");
+        chord(&mut config, 1000, 50);
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('q'), start);
+        let advance = matcher.advance(&key('w'), ms(start, 40));
+        assert_eq!(names(&matcher, &advance.matches), vec!["qw"]);
+        
+        matcher.advance(&key('w'), ms(start, 200));
+        let advance = matcher.advance(&key('q'), ms(start, 250));
+        assert_eq!(names(&matcher, &advance.matches), vec!["qw"]);
+    }
+    
+    #[test]
+    fn chord_rejects_keys_outside_tolerance() {
+        let mut config = HeroConfig::new("test");
+        chord(&mut config, 1000, 50);
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('q'), start);
+        assert!(matcher.advance(&key('w'), ms(start, 80)).matches.is_empty());
+    }
+    
+    #[test]
+    fn chord_uses_smaller_time_window() {
+        let mut config = HeroConfig::new("test");
+        chord(&mut config, 20, 50);
+        let mut matcher = matcher(&config);
+        let start = Instant::now();
+        
+        matcher.advance(&key('q'), start);
+        assert!(matcher.advance(&key('w'), ms(start, 30)).matches.is_empty());
+    }
+}
@@ -1,52 +1,207 @@
-mod combo;
 mod event_processor;
+mod executor;
+mod matcher;
 mod timing;
 
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use std::time::{Duration, Instant};
-use std::collections::{VecDeque, HashSet};
-use log::{debug, info, warn, error};
+use std::collections::{VecDeque, HashMap, HashSet};
+use log::{debug, info, warn};
 
-use crate::config::hero::{Key, ComboTrigger, ComboAction, KeyAction};
-use crate::keyboard::simulator::KeyboardSimulator;
+use crate::config::hero::{Key, ComboTrigger, KeyAction, PrefixPolicy, TriggerKind, DualRoleKey, HoldRole, SequenceOrder};
+use crate::config::EngineSettings;
+use crate::config::settings::LeaderAction;
 use crate::heroes::HeroRegistry;
+use crate::keyboard::InputEvent;
+use crate::keyboard::simulator::{ActionOutput, KeyboardSimulator};
 
 use self::event_processor::KeyEventProcessor;
-use self::timing::TimingWindow;
+use self::executor::{ComboExecutor, ComboJob};
+use self::matcher::{ComboMatch, ComboMatcher, CompiledCombo, HoldPoint};
+use self::timing::Deadline;
+
+pub use self::matcher::ComboTrie;
+
+/// 等待确认长连招无法完成的短连招
+struct PendingCombo {
+    trie: Arc<ComboTrie>,
+    matched: ComboMatch,
+    deadline: Instant,
+}
+
+/// 为等待连招完成而暂扣的按键
+struct HeldInput {
+    point: HoldPoint,
+    /// 暂扣的按键，按按下顺序
+    keys: Vec<Key>,
+    /// 暂扣期间已经松开的按键
+    released: HashSet<Key>,
+    deadline: Instant,
+}
+
+/// 序列已经完成、等最后一个键的后续状态决定是否触发的连招
+struct ArmedCombo {
+    trie: Arc<ComboTrie>,
+    matched: ComboMatch,
+    key: Key,
+    /// 长按连招触发的时间
+    deadline: Option<Instant>,
+}
+
+/// 正在计数的连按
+struct TapDance {
+    trie: Arc<ComboTrie>,
+    key: Key,
+    count: u32,
+    /// 最近一次按下匹配到的连按连招
+    candidates: Vec<ComboMatch>,
+    deadline: Instant,
+}
+
+/// 按下中的双功能键
+struct DualRoleState {
+    key: Key,
+    hold: HoldRole,
+    /// 到这个时间还没松开就按按住处理
+    deadline: Instant,
+    /// 是否已经按按住处理
+    holding: bool,
+}
+
+/// 前导键之后已经输入的按键
+struct LeaderInput {
+    keys: Vec<Key>,
+    deadline: Instant,
+}
 
 pub struct MacroEngine {
-    keyboard_simulator: Arc<KeyboardSimulator>,
+    executor: ComboExecutor,
+    /// 代替用户补发按键的模拟器，在调用线程上直接发送，不经过连招队列
+    injector: Mutex<Box<dyn ActionOutput>>,
     hero_registry: Arc<Mutex<HeroRegistry>>,
     active_hero: Arc<Mutex<String>>,
-    event_processor: Mutex<KeyEventProcessor>,
+    /// 与执行线程共享，WhileHeld动作据此判断按键是否还按着
+    event_processor: Arc<Mutex<KeyEventProcessor>>,
+    matcher: Mutex<Option<ComboMatcher>>,
     key_sequence: Mutex<VecDeque<(Key, Instant)>>,
     blocked_keys: Mutex<HashSet<Key>>,
+    pending: Mutex<Option<PendingCombo>>,
+    held: Mutex<Option<HeldInput>>,
+    armed: Mutex<Vec<ArmedCombo>>,
+    tap_dance: Mutex<Option<TapDance>>,
+    dual_roles: Mutex<Vec<DualRoleState>>,
+    leader: Mutex<Option<LeaderInput>>,
+    /// 暂停时不再匹配连招，按键原样发给游戏
+    paused: AtomicBool,
+    /// 各连招上次触发的时间，用于冷却
+    last_fired: Mutex<HashMap<String, Instant>>,
+    deadline: Deadline,
     sequence_window: Duration,
+    settings: EngineSettings,
 }
 
 impl MacroEngine {
     pub fn new(
-        keyboard_simulator: Arc<KeyboardSimulator>,
         hero_registry: Arc<Mutex<HeroRegistry>>,
         active_hero: Arc<Mutex<String>>,
+        settings: EngineSettings,
+    ) -> Self {
+        let event_processor = Arc::new(Mutex::new(KeyEventProcessor::new()));
+        let key_states = event_processor.clone();
+        
+        // 所有连招都在这一个模拟器上依次执行，WhileHeld据此判断按键是否还按着
+        let mut simulator = KeyboardSimulator::new();
+        simulator.set_rate_limit(settings.max_actions_per_second);
+        simulator.set_key_state(Box::new(move |key| key_states.lock().unwrap().is_key_down(key)));
+        
+        Self::with_outputs(
+            hero_registry,
+            active_hero,
+            settings,
+            event_processor,
+            Box::new(simulator),
+            Box::new(KeyboardSimulator::new()),
+        )
+    }
+    
+    /// 指定执行连招动作和补发按键的输出
+    fn with_outputs(
+        hero_registry: Arc<Mutex<HeroRegistry>>,
+        active_hero: Arc<Mutex<String>>,
+        settings: EngineSettings,
+        event_processor: Arc<Mutex<KeyEventProcessor>>,
+        combo_output: Box<dyn ActionOutput>,
+        injector: Box<dyn ActionOutput>,
     ) -> Self {
         let sequence_window = Duration::from_millis(1000); // 默认1秒的按键序列窗口
         
         Self {
-            keyboard_simulator,
+            executor: ComboExecutor::start(combo_output),
+            injector: Mutex::new(injector),
             hero_registry,
             active_hero,
-            event_processor: Mutex::new(KeyEventProcessor::new()),
+            event_processor,
+            matcher: Mutex::new(None),
             key_sequence: Mutex::new(VecDeque::new()),
             blocked_keys: Mutex::new(HashSet::new()),
+            pending: Mutex::new(None),
+            held: Mutex::new(None),
+            armed: Mutex::new(Vec::new()),
+            tap_dance: Mutex::new(None),
+            dual_roles: Mutex::new(Vec::new()),
+            leader: Mutex::new(None),
+            paused: AtomicBool::new(false),
+            last_fired: Mutex::new(HashMap::new()),
+            deadline: Deadline::new(),
             sequence_window,
+            settings,
         }
     }
     
+    /// 启动处理延迟决定的后台线程，引擎释放后线程自动退出
+    pub fn start_timer(engine: &Arc<MacroEngine>) {
+        let engine = Arc::downgrade(engine);
+        thread::spawn(move || {
+            while let Some(engine) = engine.upgrade() {
+                if engine.deadline.wait(Duration::from_millis(500)) {
+                    engine.on_deadline(Instant::now());
+                }
+            }
+        });
+    }
+    
+    /// 处理键盘或鼠标的输入事件，返回是否屏蔽这个事件
+    pub fn process_input(&self, event: InputEvent) -> bool {
+        self.process_key_event(event.key, event.is_down)
+    }
+    
     pub fn process_key_event(&self, key: Key, is_down: bool) -> bool {
         info!("宏引擎处理按键: {:?}, 状态: {}", key, if is_down { "按下" } else { "释放" });
+        let now = Instant::now();
         
         if !is_down {
+            if let Some(held_for) = self.event_processor.lock().unwrap().release(&key, now) {
+                debug!("按键 {:?} 按住了 {}ms", key, held_for.as_millis());
+                self.fire_armed_on_release(&key, Some(held_for));
+            } else {
+                self.fire_armed_on_release(&key, None);
+            }
+            
+            if self.release_dual_role(&key) {
+                return true;
+            }
+            
+            // 暂扣期间松开的按键同样先扣下，补发时一起发送
+            if let Some(held) = self.held.lock().unwrap().as_mut() {
+                if held.keys.contains(&key) {
+                    debug!("暂扣按键已松开: {:?}", key);
+                    held.released.insert(key);
+                    return true;
+                }
+            }
+            
             // 处理键松开
             let mut blocked_keys = self.blocked_keys.lock().unwrap();
             if blocked_keys.contains(&key) {
@@ -58,117 +213,918 @@ impl MacroEngine {
         }
         
         // 更新事件处理器中的按键状态
-        {
+        let is_repeat = {
             let mut processor = self.event_processor.lock().unwrap();
-            processor.set_key_state(key.clone(), true);
             debug!("更新按键状态: {:?} = {}", key, true);
+            processor.press(&key, now)
+        };
+        
+        if is_repeat {
+            debug!("按键自动重复: {:?}", key);
+            return self.process_repeat(&key);
+        }
+        
+        // 连按计数中按下了别的键，按已有的次数立即决定
+        self.interrupt_tap_dance(&key);
+        
+        // 双功能键还没有决定时按下了其他键，按按住处理
+        let settled = self.settle_dual_roles(&key);
+        let blocked = self.process_press(key.clone(), now);
+        
+        // 刚按下的修饰键要先于当前按键到达游戏，会放行的当前按键改为跟在修饰键后面补发
+        if settled && !blocked {
+            self.inject("补发修饰键之后的按键", vec![KeyAction::Down(key)]);
+            return true;
+        }
+        blocked
+    }
+    
+    /// 处理一次新的按下 (不是自动重复)，返回是否屏蔽
+    fn process_press(&self, key: Key, now: Instant) -> bool {
+        // 前导键和之后输入的命令不发给游戏
+        if self.process_leader(&key, now) {
+            self.blocked_keys.lock().unwrap().insert(key);
+            return true;
+        }
+        
+        if self.is_paused() {
+            return false;
         }
         
         // 检查是否是英雄切换快捷键
         if self.check_hero_switch_hotkey(&key) {
             info!("触发英雄切换快捷键: {:?}", key);
+            self.blocked_keys.lock().unwrap().insert(key);
             return true;
         }
         
+        // 全局取消快捷键
+        if self.settings.cancel_hotkey.as_ref() == Some(&key) {
+            info!("触发取消快捷键: {:?}", key);
+            self.cancel_combos();
+            self.blocked_keys.lock().unwrap().insert(key);
+            return true;
+        }
+        
+        // 检查当前活跃英雄的连招
+        let (hero_name, trie) = match self.active_trie() {
+            Some(active) => active,
+            None => return false,
+        };
+        
+        // 双功能键先扣下，松开或超时后再决定
+        if let Some(dual) = trie.dual_role(&key) {
+            self.press_dual_role(dual, now);
+            return true;
+        }
+        
+        // 英雄忽略的按键不进入按键序列，照常发给游戏
+        if trie.is_ignored(&key) {
+            debug!("忽略按键: {:?}", key);
+            return false;
+        }
+        
+        // 重置键清空所有进行中的匹配，按键本身照常发给游戏
+        if trie.is_reset_key(&key) {
+            info!("按下重置键 {:?}，清空按键序列", key);
+            return self.reset_sequence(Some(&key));
+        }
+        
         // 处理按键序列
-        self.update_key_sequence(&key);
+        self.update_key_sequence(&key, now);
         let current_sequence = self.get_current_sequence();
         info!("当前按键序列: {:?}", current_sequence);
         
-        // 检查当前活跃英雄的连招
+        let (matches, hold, kept, pending_alive, stale_held) = {
+            let mut matcher = self.matcher.lock().unwrap();
+            
+            // 英雄切换或重新注册后换用新的前缀树，旧的部分匹配作废，暂扣的按键原样补发
+            let mut stale_held = None;
+            if !matcher.as_ref().is_some_and(|m| m.is_compiled_from(&trie)) {
+                debug!("为英雄 [{}] 创建新的连招匹配器", hero_name);
+                *matcher = Some(ComboMatcher::new(trie.clone(), self.sequence_window));
+                *self.pending.lock().unwrap() = None;
+                self.armed.lock().unwrap().clear();
+                *self.tap_dance.lock().unwrap() = None;
+                self.last_fired.lock().unwrap().clear();
+                stale_held = self.held.lock().unwrap().take();
+            }
+            let matcher = matcher.as_mut().unwrap();
+            
+            let advance = matcher.advance(&key, now);
+            let mut matches = advance.matches;
+            
+            // 需要同时按住的连招
+            let processor = self.event_processor.lock().unwrap();
+            for id in trie.held_combos(&key) {
+                let trigger = &trie.combo(id).trigger;
+                if !matches.iter().any(|m| m.id == id) && trigger.is_held(&|key| processor.is_key_down(key)) {
+                    matches.push(ComboMatch::held(id, trigger.sequence.len(), now));
+                }
+            }
+            
+            let pending_alive = match &*self.pending.lock().unwrap() {
+                Some(pending) => matcher.can_extend(&pending.matched),
+                None => false,
+            };
+            (matches, advance.hold, advance.kept, pending_alive, stale_held)
+        };
+        
+        if let Some(held) = stale_held {
+            self.replay_held(held, None);
+        }
+        self.resolve_pending(&trie, &matches, pending_alive, now);
+        
+        // 冷却中、不属于当前层、修饰键或条件不满足和和弦按键没有按住的连招不参与选择
+        let layer = self.active_layer();
+        let matches: Vec<ComboMatch> = matches.into_iter()
+            .filter(|m| trie.combo(m.id).trigger.layer == layer && self.modifiers_satisfied(trie.combo(m.id)))
+            .filter(|m| self.chord_held(trie.combo(m.id)) && self.condition_holds(trie.combo(m.id), m))
+            .filter(|m| !self.is_cooling_down(trie.combo(m.id), now))
+            .collect();
+        
+        // 点按、长按和松开触发的连招要等最后一个键的后续状态才能决定
+        let (matches, deferred): (Vec<ComboMatch>, Vec<ComboMatch>) = matches.into_iter()
+            .partition(|m| trie.combo(m.id).trigger.kind == TriggerKind::Press);
+        let (dances, deferred): (Vec<ComboMatch>, Vec<ComboMatch>) = deferred.into_iter()
+            .partition(|m| matches!(trie.combo(m.id).trigger.kind, TriggerKind::TapDance { .. }));
+        let armed_block = self.arm_combos(&trie, deferred, &key, now) | self.count_tap(&trie, dances, &key, now);
+        let winner = trie.resolve(&matches);
+        
+        // 先决定之前暂扣的按键: 连招完成则丢弃，序列继续则一起暂扣，仍在等待则放行当前按键，否则补发
+        let previous = self.held.lock().unwrap().take();
+        let mut hold = hold;
+        let mut reinjected = false;
+        if let Some(mut held) = previous {
+            match (winner, hold.take()) {
+                (Some(winner), _) if trie.completes_hold(winner, &held.point) => {
+                    info!("连招完成，丢弃暂扣的按键: {:?}", held.keys);
+                    // 游戏没有收到这些按键的按下，仍按住的按键松开时也要屏蔽
+                    let mut blocked = self.blocked_keys.lock().unwrap();
+                    for key in held.keys.iter().filter(|key| !held.released.contains(*key)) {
+                        blocked.insert(key.clone());
+                    }
+                }
+                (None, Some(point)) if trie.continues_hold(&point, &held.point) => {
+                    debug!("序列继续，暂扣按键: {:?}", key);
+                    held.deadline = now + Duration::from_millis(point.timeout);
+                    held.point = point;
+                    held.keys.push(key);
+                    self.deadline.schedule(held.deadline);
+                    *self.held.lock().unwrap() = Some(held);
+                    return true;
+                }
+                (None, _) if trie.keeps_hold(&kept, &held.point) => {
+                    // 被跳过或夹杂的按键不推进序列，照常发给游戏，暂扣位置和超时都不变
+                    debug!("序列仍在等待，放行按键: {:?}", key);
+                    *self.held.lock().unwrap() = Some(held);
+                    return false;
+                }
+                (_, point) => {
+                    // 补发的按键必须排在当前按键前面，会放行的当前按键也一起补发
+                    let passes = match winner {
+                        Some(winner) => !trie.combo(winner.id).action.block_original,
+                        None => point.is_none() && !armed_block,
+                    };
+                    reinjected = passes;
+                    self.replay_held(held, if passes { Some(&key) } else { None });
+                    hold = point;
+                }
+            }
+        }
+        
+        let winner = match winner {
+            Some(winner) => winner,
+            None => {
+                // 可能是需要暂扣按键的连招的开头
+                if let Some(point) = hold {
+                    debug!("暂扣按键 {:?}，最多等待 {}ms", key, point.timeout);
+                    let deadline = now + Duration::from_millis(point.timeout);
+                    *self.held.lock().unwrap() = Some(HeldInput {
+                        point,
+                        keys: vec![key],
+                        released: HashSet::new(),
+                        deadline,
+                    });
+                    self.deadline.schedule(deadline);
+                    return true;
+                }
+                return reinjected || armed_block;
+            }
+        };
+        let combo = trie.combo(winner.id);
+        
+        // 还有以此为前缀的更长连招时按英雄的前缀策略处理
+        match (trie.prefix_policy(), winner.extendable_until) {
+            (PrefixPolicy::Longer, Some(deadline)) => {
+                info!("连招 {} 是更长连招的前缀，等待至多 {}ms",
+                      combo.name, deadline.saturating_duration_since(now).as_millis());
+                *self.pending.lock().unwrap() = Some(PendingCombo {
+                    trie: trie.clone(),
+                    matched: winner.clone(),
+                    deadline,
+                });
+                self.deadline.schedule(deadline);
+            }
+            (PrefixPolicy::Shorter, Some(_)) => {
+                self.fire_combo(&trie, winner);
+                debug!("连招 {} 已触发，放弃其余未完成的匹配", combo.name);
+                if let Some(matcher) = self.matcher.lock().unwrap().as_mut() {
+                    matcher.reset();
+                }
+            }
+            _ => self.fire_combo(&trie, winner),
+        }
+        
+        self.block_trigger_keys(&combo.trigger);
+        if combo.action.block_original {
+            // 按下被屏蔽的按键，之后的自动重复和松开也一起屏蔽
+            self.blocked_keys.lock().unwrap().insert(key);
+        }
+        combo.action.block_original || reinjected || armed_block
+    }
+    
+    fn active_trie(&self) -> Option<(String, Arc<ComboTrie>)> {
         let hero_name = self.active_hero.lock().unwrap().clone();
         info!("检查英雄 [{}] 的连招", hero_name);
-        let hero_registry = self.hero_registry.lock().unwrap();
-        
-        if let Some(hero_config) = hero_registry.get_hero(&hero_name) {
-            debug!("找到英雄 [{}] 的配置，检查连招", hero_name);
-            // 检查是否触发了任何连招
-            for (combo_name, (trigger, action)) in &hero_config.combos {
-                debug!("检查连招: {} 触发条件: {:?}", combo_name, trigger.sequence);
-                
-                if self.check_combo_trigger(trigger) {
-                    info!("触发连招: {}", combo_name);
-                    
-                    // 创建新的键盘模拟器实例
-                    let mut simulator = KeyboardSimulator::new();
-                    
-                    // 执行连招动作
-                    debug!("执行连招动作: {:?}", action.keys);
-                    simulator.execute_actions(&action.keys);
-                    
-                    // 更新屏蔽按键
-                    if !trigger.block_keys.is_empty() {
-                        debug!("设置屏蔽按键: {:?}", trigger.block_keys);
-                        let mut blocked = self.blocked_keys.lock().unwrap();
-                        for key in &trigger.block_keys {
-                            blocked.insert(key.clone());
-                        }
-                    }
-                    
-                    return action.block_original;
+        match self.hero_registry.lock().unwrap().get_matcher(&hero_name) {
+            Some(trie) => Some((hero_name, trie)),
+            None => {
+                warn!("未找到英雄 [{}] 的配置", hero_name);
+                None
+            }
+        }
+    }
+    
+    /// 处理按住不放时系统自动重复的按下: 默认不参与匹配，只有允许重复的连招会看到它
+    fn process_repeat(&self, key: &Key) -> bool {
+        // 按下中的双功能键由松开决定
+        if self.dual_roles.lock().unwrap().iter().any(|dual| &dual.key == key) {
+            return true;
+        }
+        
+        // 暂扣的按键，重复的按下也一起扣下
+        if self.held.lock().unwrap().as_ref().is_some_and(|held| held.keys.contains(key)) {
+            return true;
+        }
+        
+        // 被屏蔽的按键，没有触发允许重复的连招时重复的按下也一起屏蔽
+        let blocked = self.blocked_keys.lock().unwrap().contains(key);
+        
+        // 暂停时不触发任何连招，只保持之前的屏蔽
+        if self.is_paused() {
+            return blocked;
+        }
+        
+        let (_, trie) = match self.active_trie() {
+            Some(active) => active,
+            None => return blocked,
+        };
+        
+        let now = Instant::now();
+        let mut matches = match self.matcher.lock().unwrap().as_ref() {
+            Some(matcher) if matcher.is_compiled_from(&trie) => matcher.repeat(key, now),
+            _ => Vec::new(),
+        };
+        {
+            let processor = self.event_processor.lock().unwrap();
+            for id in trie.held_combos(key) {
+                let trigger = &trie.combo(id).trigger;
+                if trigger.allow_repeat && !matches.iter().any(|m| m.id == id) && trigger.is_held(&|key| processor.is_key_down(key)) {
+                    matches.push(ComboMatch::held(id, trigger.sequence.len(), now));
                 }
             }
-        } else {
-            warn!("未找到英雄 [{}] 的配置", hero_name);
         }
+        let layer = self.active_layer();
+        matches.retain(|m| {
+            let combo = trie.combo(m.id);
+            combo.trigger.kind == TriggerKind::Press && combo.trigger.layer == layer
+                && self.modifiers_satisfied(combo) && self.condition_holds(combo, m) && !self.is_cooling_down(combo, now)
+        });
         
-        false
+        match trie.resolve(&matches) {
+            Some(winner) => {
+                let combo = trie.combo(winner.id);
+                self.fire_combo(&trie, winner);
+                self.block_trigger_keys(&combo.trigger);
+                combo.action.block_original || blocked
+            }
+            None => blocked,
+        }
     }
     
-    fn check_combo_trigger(&self, trigger: &ComboTrigger) -> bool {
-        // 检查是否满足时间窗口条件
-        if let Some(time_window) = trigger.time_window {
-            debug!("检查时间窗口连招，窗口: {}ms", time_window);
-            // 从按键序列中检查是否按下了触发按键序列
-            let sequence = self.key_sequence.lock().unwrap();
+    /// 根据本次按键的匹配结果决定等待中的短连招: 被更长连招取代、继续等待或立即触发
+    fn resolve_pending(&self, trie: &Arc<ComboTrie>, matches: &[ComboMatch], pending_alive: bool, now: Instant) {
+        let mut pending = self.pending.lock().unwrap();
+        let waiting = match pending.take() {
+            Some(waiting) => waiting,
+            None => return,
+        };
+        let name = &waiting.trie.combo(waiting.matched.id).name;
+        
+        if matches.iter().any(|m| trie.extends(m, &waiting.matched)) {
+            info!("更长的连招已完成，放弃等待中的连招 {}", name);
+        } else if pending_alive && now < waiting.deadline {
+            *pending = Some(waiting);
+        } else {
+            drop(pending);
+            self.fire_combo(&waiting.trie, &waiting.matched);
+        }
+    }
+    
+    /// 记下等待按键后续状态的连招，返回是否需要屏蔽这次按下
+    fn arm_combos(&self, trie: &Arc<ComboTrie>, matches: Vec<ComboMatch>, key: &Key, now: Instant) -> bool {
+        let mut block = false;
+        let mut armed = self.armed.lock().unwrap();
+        
+        for matched in matches {
+            let combo = trie.combo(matched.id);
+            let deadline = match combo.trigger.kind {
+                TriggerKind::LongPress { min_ms } => Some(now + Duration::from_millis(min_ms)),
+                _ => None,
+            };
+            debug!("连招 {} 的序列已完成，等待按键 {:?} 的{}", combo.name, key,
+                   if deadline.is_some() { "长按" } else { "松开" });
             
-            // 检查序列是否为空
-            if sequence.len() < trigger.sequence.len() {
-                debug!("按键序列长度不足");
-                return false;
+            if let Some(deadline) = deadline {
+                self.deadline.schedule(deadline);
             }
+            block |= combo.action.block_original;
+            armed.push(ArmedCombo {
+                trie: trie.clone(),
+                matched,
+                key: key.clone(),
+                deadline,
+            });
+        }
+        drop(armed);
+        
+        if block {
+            // 按下被屏蔽后，松开也要一起屏蔽
+            self.blocked_keys.lock().unwrap().insert(key.clone());
+        }
+        block
+    }
+    
+    /// 按键松开时决定等待中的点按和松开连招，held_for是这次按住的时长
+    fn fire_armed_on_release(&self, key: &Key, held_for: Option<Duration>) {
+        let released: Vec<ArmedCombo> = {
+            let mut armed = self.armed.lock().unwrap();
+            let (released, rest) = armed.drain(..).partition(|a| &a.key == key);
+            *armed = rest;
+            released
+        };
+        
+        for armed in released {
+            let combo = armed.trie.combo(armed.matched.id);
+            let fire = match combo.trigger.kind {
+                TriggerKind::Tap { max_ms } => {
+                    held_for.is_some_and(|held_for| held_for <= Duration::from_millis(max_ms))
+                }
+                TriggerKind::Release => true,
+                // 松开时还没有触发说明按住的时间不够
+                TriggerKind::LongPress { .. } | TriggerKind::Press | TriggerKind::TapDance { .. } => false,
+            };
             
-            // 创建一个映射来跟踪每个按键的最后出现时间
-            let mut key_times = std::collections::HashMap::new();
-            for (key, time) in sequence.iter() {
-                key_times.insert(key, *time);
+            if fire {
+                self.fire_combo(&armed.trie, &armed.matched);
+            } else {
+                debug!("按键 {:?} 的按住时长不满足连招 {}", key, combo.name);
             }
-            
-            // 检查所有触发键是否都存在
-            for key in &trigger.sequence {
-                if !key_times.contains_key(key) {
-                    debug!("按键 {:?} 不在序列中", key);
-                    return false;
+        }
+    }
+    
+    /// 记一次连按，返回是否需要屏蔽这次按下
+    fn count_tap(&self, trie: &Arc<ComboTrie>, matches: Vec<ComboMatch>, key: &Key, now: Instant) -> bool {
+        if matches.is_empty() {
+            return false;
+        }
+        
+        let previous = self.tap_dance.lock().unwrap().take();
+        let count = match previous {
+            Some(dance) if &dance.key == key && now < dance.deadline => dance.count + 1,
+            Some(dance) => {
+                self.finish_tap_dance(dance);
+                1
+            }
+            None => 1,
+        };
+        
+        let mut window = 0;
+        let mut max_taps = 0;
+        let mut block = false;
+        for matched in &matches {
+            let combo = trie.combo(matched.id);
+            if let TriggerKind::TapDance { taps, window_ms } = combo.trigger.kind {
+                window = window.max(window_ms);
+                max_taps = max_taps.max(taps);
+            }
+            block |= combo.action.block_original;
+        }
+        if block {
+            self.blocked_keys.lock().unwrap().insert(key.clone());
+        }
+        
+        let dance = TapDance {
+            trie: trie.clone(),
+            key: key.clone(),
+            count,
+            candidates: matches,
+            deadline: now + Duration::from_millis(window),
+        };
+        
+        // 已经到了最多的次数，不用再等
+        if count >= max_taps {
+            self.finish_tap_dance(dance);
+        } else {
+            debug!("按键 {:?} 连按 {} 次，最多等待 {}ms", key, count, window);
+            self.deadline.schedule(dance.deadline);
+            *self.tap_dance.lock().unwrap() = Some(dance);
+        }
+        block
+    }
+    
+    /// 按下了别的键时结束正在计数的连按
+    fn interrupt_tap_dance(&self, key: &Key) {
+        let interrupted = {
+            let mut dance = self.tap_dance.lock().unwrap();
+            match dance.as_ref() {
+                Some(current) if &current.key != key => dance.take(),
+                _ => None,
+            }
+        };
+        
+        if let Some(dance) = interrupted {
+            debug!("按下了 {:?}，结束按键 {:?} 的连按", key, dance.key);
+            self.finish_tap_dance(dance);
+        }
+    }
+    
+    /// 按连按的次数选出要触发的连招
+    fn finish_tap_dance(&self, dance: TapDance) {
+        let candidates: Vec<ComboMatch> = dance.candidates.into_iter()
+            .filter(|m| matches!(dance.trie.combo(m.id).trigger.kind,
+                                 TriggerKind::TapDance { taps, .. } if taps == dance.count))
+            .collect();
+        
+        match dance.trie.resolve(&candidates) {
+            Some(winner) => self.fire_combo(&dance.trie, winner),
+            None => debug!("按键 {:?} 连按 {} 次，没有对应的连招", dance.key, dance.count),
+        }
+    }
+    
+    /// 处理前导键和之后的命令输入，返回按键是否属于前导序列
+    fn process_leader(&self, key: &Key, now: Instant) -> bool {
+        let leader = match &self.settings.leader {
+            Some(leader) => leader,
+            None => return false,
+        };
+        
+        let mut input = self.leader.lock().unwrap();
+        let keys = match input.take() {
+            Some(current) if now <= current.deadline => Some(current.keys),
+            Some(current) => {
+                debug!("前导序列超时，放弃输入: {:?}", current.keys);
+                None
+            }
+            None => None,
+        };
+        let mut keys = match keys {
+            Some(keys) => keys,
+            None if key == &leader.key => {
+                info!("按下前导键 {:?}，等待命令", key);
+                let deadline = now + Duration::from_millis(leader.timeout);
+                *input = Some(LeaderInput {
+                    keys: Vec::new(),
+                    deadline,
+                });
+                self.deadline.schedule(deadline);
+                return true;
+            }
+            None => return false,
+        };
+        
+        keys.push(key.clone());
+        
+        if let Some(command) = leader.commands.iter().find(|command| command.sequence == keys) {
+            drop(input);
+            self.run_leader_action(&command.action);
+        } else if leader.commands.iter().any(|command| command.sequence.starts_with(&keys)) {
+            let deadline = now + Duration::from_millis(leader.timeout);
+            *input = Some(LeaderInput {
+                keys,
+                deadline,
+            });
+            self.deadline.schedule(deadline);
+        } else {
+            info!("没有匹配的前导命令: {:?}", keys);
+        }
+        true
+    }
+    
+    fn run_leader_action(&self, action: &LeaderAction) {
+        info!("执行前导命令: {:?}", action);
+        match action {
+            LeaderAction::SwitchHero(name) => {
+                self.switch_hero(name);
+            }
+            LeaderAction::TogglePause => self.set_paused(!self.is_paused()),
+            LeaderAction::CancelCombos => self.cancel_combos(),
+        }
+    }
+    
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+    
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.swap(paused, Ordering::SeqCst) == paused {
+            return;
+        }
+        
+        if paused {
+            info!("连招已暂停");
+            self.reset_sequence(None);
+        } else {
+            info!("连招已恢复");
+        }
+    }
+    
+    fn press_dual_role(&self, dual: &DualRoleKey, now: Instant) {
+        debug!("按下双功能键 {:?}，{}ms内松开视为点按", dual.key, dual.hold_after);
+        let deadline = now + Duration::from_millis(dual.hold_after);
+        self.dual_roles.lock().unwrap().push(DualRoleState {
+            key: dual.key.clone(),
+            hold: dual.hold.clone(),
+            deadline,
+            holding: false,
+        });
+        self.deadline.schedule(deadline);
+    }
+    
+    /// 还没有决定的双功能键都按按住处理，except是刚按下的按键；返回是否因此按下了修饰键
+    fn settle_dual_roles(&self, except: &Key) -> bool {
+        let mut pressed = false;
+        let mut dual_roles = self.dual_roles.lock().unwrap();
+        for dual in dual_roles.iter_mut().filter(|dual| !dual.holding && &dual.key != except) {
+            pressed |= self.activate_dual_role(dual);
+        }
+        pressed
+    }
+    
+    /// 按按住处理双功能键，返回是否按下了修饰键
+    fn activate_dual_role(&self, dual: &mut DualRoleState) -> bool {
+        dual.holding = true;
+        match &dual.hold {
+            HoldRole::Modifier(modifier) => {
+                info!("双功能键 {:?} 按住，按下 {:?}", dual.key, modifier);
+                self.event_processor.lock().unwrap().press_virtual(modifier);
+                self.inject("双功能键按住", vec![KeyAction::Down(modifier.clone())]);
+                true
+            }
+            HoldRole::Layer(layer) => {
+                info!("双功能键 {:?} 按住，启用层 {}", dual.key, layer);
+                false
+            }
+        }
+    }
+    
+    fn deactivate_dual_role(&self, dual: &DualRoleState) {
+        match &dual.hold {
+            HoldRole::Modifier(modifier) => {
+                info!("双功能键 {:?} 松开，松开 {:?}", dual.key, modifier);
+                self.event_processor.lock().unwrap().release_virtual(modifier);
+                self.inject("双功能键松开", vec![KeyAction::Up(modifier.clone())]);
+            }
+            HoldRole::Layer(layer) => info!("双功能键 {:?} 松开，停用层 {}", dual.key, layer),
+        }
+    }
+    
+    /// 双功能键松开: 按住过则结束按住的作用，否则补发一次点按；不是双功能键时返回false
+    fn release_dual_role(&self, key: &Key) -> bool {
+        let released = {
+            let mut dual_roles = self.dual_roles.lock().unwrap();
+            match dual_roles.iter().position(|dual| &dual.key == key) {
+                Some(index) => dual_roles.remove(index),
+                None => return false,
+            }
+        };
+        
+        if released.holding {
+            self.deactivate_dual_role(&released);
+        } else {
+            debug!("双功能键 {:?} 点按", key);
+            self.inject("双功能键点按", vec![KeyAction::Down(key.clone()), KeyAction::Up(key.clone())]);
+        }
+        true
+    }
+    
+    /// 当前按住的双功能键启用的层，多个时以最后按下的为准
+    fn active_layer(&self) -> Option<String> {
+        self.dual_roles.lock().unwrap().iter().rev().find_map(|dual| match &dual.hold {
+            HoldRole::Layer(layer) if dual.holding => Some(layer.clone()),
+            _ => None,
+        })
+    }
+    
+    /// 代替用户发出按键: 在当前线程立即发送，不排在连招后面，也不受速率限制和取消的影响，
+    /// 这样游戏收到的顺序和用户实际按下的顺序一致
+    fn inject(&self, name: &str, actions: Vec<KeyAction>) {
+        debug!("{}: {:?}", name, actions);
+        self.injector.lock().unwrap().execute_actions(&actions);
+    }
+    
+    /// 把暂扣的按键按原来的顺序补发给游戏，trailing是紧随其后需要补发按下的当前按键
+    fn replay_held(&self, held: HeldInput, trailing: Option<&Key>) {
+        info!("补发暂扣的按键: {:?}", held.keys);
+        
+        let mut actions = Vec::new();
+        for key in &held.keys {
+            actions.push(KeyAction::Down(key.clone()));
+            if held.released.contains(key) {
+                actions.push(KeyAction::Up(key.clone()));
+            }
+        }
+        if let Some(key) = trailing {
+            actions.push(KeyAction::Down(key.clone()));
+        }
+        
+        self.inject("补发暂扣按键", actions);
+    }
+    
+    /// 后台线程在截止时间到达时调用
+    fn on_deadline(&self, now: Instant) {
+        {
+            // 补发完成之前一直持有锁，钩子线程上这些按键的松开要等补发之后才能放行
+            let mut held = self.held.lock().unwrap();
+            match held.as_ref() {
+                Some(input) if input.deadline <= now => {
+                    debug!("等待超时，连招没有完成");
+                    self.replay_held(held.take().unwrap(), None);
                 }
+                Some(input) => self.deadline.schedule(input.deadline),
+                None => {}
             }
-            
-            // 检查按键之间的时间差是否在时间窗口内
-            let first_key_time = *key_times.get(&trigger.sequence[0]).unwrap();
-            for i in 1..trigger.sequence.len() {
-                let key_time = *key_times.get(&trigger.sequence[i]).unwrap();
-                let diff = key_time.duration_since(first_key_time).as_millis();
-                debug!("按键 {:?} 和 {:?} 时间差: {}ms", trigger.sequence[0], trigger.sequence[i], diff);
-                
-                if diff > time_window as u128 {
-                    debug!("时间差超过窗口");
-                    return false;
+        }
+        
+        let expired = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.as_ref() {
+                Some(waiting) if waiting.deadline <= now => pending.take(),
+                Some(waiting) => {
+                    self.deadline.schedule(waiting.deadline);
+                    None
                 }
+                None => None,
             }
-            
-            debug!("满足时间窗口条件");
-            return true;
-        } else {
-            // 检查是否同时按下了所有触发键
-            debug!("检查同时按键连招");
+        };
+        
+        if let Some(waiting) = expired {
+            debug!("等待超时，更长的连招没有完成");
+            self.fire_combo(&waiting.trie, &waiting.matched);
+        }
+        
+        // 按住时间已经足够的长按连招
+        let long_pressed: Vec<ArmedCombo> = {
+            let mut armed = self.armed.lock().unwrap();
+            let (due, rest) = armed.drain(..).partition(|a| a.deadline.is_some_and(|deadline| deadline <= now));
+            *armed = rest;
+            if let Some(next) = armed.iter().filter_map(|a| a.deadline).min() {
+                self.deadline.schedule(next);
+            }
+            due
+        };
+        
+        // 以按键状态记录的按下时间为准，校正过状态的按键从校正时开始计时
+        for mut armed in long_pressed {
+            let combo = armed.trie.combo(armed.matched.id);
+            let min_hold = match combo.trigger.kind {
+                TriggerKind::LongPress { min_ms } => Duration::from_millis(min_ms),
+                _ => Duration::ZERO,
+            };
+            let held_for = self.event_processor.lock().unwrap().held_for(&armed.key, now);
+            match held_for {
+                Some(held_for) if held_for >= min_hold => {
+                    debug!("按键 {:?} 已长按", armed.key);
+                    self.fire_combo(&armed.trie, &armed.matched);
+                }
+                Some(held_for) => {
+                    let deadline = now + (min_hold - held_for);
+                    armed.deadline = Some(deadline);
+                    self.deadline.schedule(deadline);
+                    self.armed.lock().unwrap().push(armed);
+                }
+                None => debug!("按键 {:?} 已经松开，放弃长按连招 {}", armed.key, combo.name),
+            }
+        }
+        
+        {
+            let mut dual_roles = self.dual_roles.lock().unwrap();
+            for dual in dual_roles.iter_mut().filter(|dual| !dual.holding) {
+                if dual.deadline <= now {
+                    self.activate_dual_role(dual);
+                } else {
+                    self.deadline.schedule(dual.deadline);
+                }
+            }
+        }
+        
+        {
+            let mut leader = self.leader.lock().unwrap();
+            match leader.as_ref() {
+                Some(input) if input.deadline <= now => {
+                    info!("前导序列超时，放弃输入: {:?}", input.keys);
+                    *leader = None;
+                }
+                Some(input) => self.deadline.schedule(input.deadline),
+                None => {}
+            }
+        }
+        
+        let finished = {
+            let mut dance = self.tap_dance.lock().unwrap();
+            match dance.as_ref() {
+                Some(current) if current.deadline <= now => dance.take(),
+                Some(current) => {
+                    self.deadline.schedule(current.deadline);
+                    None
+                }
+                None => None,
+            }
+        };
+        
+        if let Some(dance) = finished {
+            debug!("连按等待结束");
+            self.finish_tap_dance(dance);
+        }
+    }
+    
+    fn fire_combo(&self, trie: &Arc<ComboTrie>, matched: &ComboMatch) {
+        let combo = trie.combo(matched.id);
+        info!("触发连招: {}", combo.name);
+        
+        if combo.trigger.cooldown.is_some() {
+            self.last_fired.lock().unwrap().insert(combo.name.clone(), Instant::now());
+        }
+        
+        // 交给执行线程，钩子回调不等待动作完成
+        debug!("执行连招动作: {:?}", combo.action.keys);
+        self.executor.submit(ComboJob {
+            name: combo.name.clone(),
+            actions: combo.action.keys.clone(),
+            timing: combo.timing,
+            policy: combo.action.on_busy,
+        });
+        
+        if combo.trigger.consume {
+            self.consume_keys(trie, matched);
+        }
+    }
+    
+    fn is_cooling_down(&self, combo: &CompiledCombo, now: Instant) -> bool {
+        let cooldown = match combo.trigger.cooldown {
+            Some(cooldown) => Duration::from_millis(cooldown),
+            None => return false,
+        };
+        
+        match self.last_fired.lock().unwrap().get(&combo.name) {
+            Some(&fired) if now.saturating_duration_since(fired) < cooldown => {
+                info!("连招 {} 冷却中，忽略这次触发 (剩余 {}ms)",
+                      combo.name, (cooldown - now.saturating_duration_since(fired)).as_millis());
+                true
+            }
+            _ => false,
+        }
+    }
+    
+    /// 当前按住的修饰键是否满足连招的要求
+    fn modifiers_satisfied(&self, combo: &CompiledCombo) -> bool {
+        let processor = self.event_processor.lock().unwrap();
+        combo.trigger.modifiers.is_satisfied(&|key| processor.is_key_down(key))
+    }
+    
+    /// 按当前的按键状态和按键序列检查连招的额外条件
+    fn condition_holds(&self, combo: &CompiledCombo, matched: &ComboMatch) -> bool {
+        let condition = match &combo.trigger.condition {
+            Some(condition) => condition,
+            None => return true,
+        };
+        
+        let processor = self.event_processor.lock().unwrap();
+        let sequence = self.key_sequence.lock().unwrap();
+        let holds = condition.evaluate(
+            &|key| processor.is_key_down(key),
+            &|key, from, to| match (matched.times.get(from), matched.times.get(to)) {
+                (Some(&start), Some(&end)) => {
+                    sequence.iter().any(|(pressed, time)| pressed == key && *time > start && *time < end)
+                }
+                _ => false,
+            },
+        );
+        
+        if !holds {
+            debug!("连招 {} 的条件不满足", combo.name);
+        }
+        holds
+    }
+    
+    /// 和弦完成时所有按键都必须还按住
+    fn chord_held(&self, combo: &CompiledCombo) -> bool {
+        match combo.trigger.order {
+            SequenceOrder::Chord { .. } => {
+                let processor = self.event_processor.lock().unwrap();
+                combo.trigger.is_held(&|key| processor.is_key_down(key))
+            }
+            _ => true,
+        }
+    }
+    
+    /// 把匹配用过的按键从序列和部分匹配中移除，避免同一组按键再次触发连招
+    fn consume_keys(&self, trie: &Arc<ComboTrie>, matched: &ComboMatch) {
+        if let Some(matcher) = self.matcher.lock().unwrap().as_mut() {
+            if matcher.is_compiled_from(trie) {
+                matcher.consume(matched);
+            }
+        }
+        
+        let mut sequence = self.key_sequence.lock().unwrap();
+        sequence.retain(|(_, time)| !matched.times.contains(time));
+        debug!("已消耗连招使用的 {} 个按键", matched.times.len());
+    }
+    
+    /// 取消正在执行、排队和等待中的连招
+    pub fn cancel_combos(&self) {
+        *self.pending.lock().unwrap() = None;
+        self.armed.lock().unwrap().clear();
+        *self.tap_dance.lock().unwrap() = None;
+        self.executor.cancel();
+    }
+    
+    /// 按实际的物理按键状态校正记录，用于钩子重新安装或窗口切换后错过了按键事件的情况；
+    /// keys是可以查询实际状态的按键，之前没有记录过的也会校正
+    pub fn resync_key_states(&self, keys: &[Key], is_physically_down: &dyn Fn(&Key) -> bool) {
+        let changed = self.event_processor.lock().unwrap().resync(keys, is_physically_down, Instant::now());
+        if changed.is_empty() {
+            return;
+        }
+        
+        info!("按键状态已校正: {:?}", changed);
+        
+        // 错过了松开事件的按键不会再收到对应的松开，不能继续等着屏蔽它
+        {
             let processor = self.event_processor.lock().unwrap();
-            let result = processor.are_keys_down(&trigger.sequence);
-            debug!("同时按键检查结果: {}", result);
-            return result;
+            self.blocked_keys.lock().unwrap().retain(|key| !changed.contains(key) || processor.is_key_down(key));
+        }
+        
+        // 松开被错过的双功能键不补发点按，只结束按住的作用；
+        // 记录中的双功能键都是按下的，状态变化说明已经松开
+        let released: Vec<DualRoleState> = {
+            let mut dual_roles = self.dual_roles.lock().unwrap();
+            let (released, rest) = dual_roles.drain(..).partition(|dual| changed.contains(&dual.key));
+            *dual_roles = rest;
+            released
+        };
+        for dual in released.iter().filter(|dual| dual.holding) {
+            self.deactivate_dual_role(dual);
         }
+        
+        // 错过的事件可能让进行中的匹配失真，重新开始
+        self.reset_sequence(None);
     }
     
-    fn update_key_sequence(&self, key: &Key) {
-        let now = Instant::now();
+    /// 清空按键序列、部分匹配和等待中的连招，暂扣的按键原样补发；
+    /// 返回trailing是否因此跟在暂扣的按键后面补发
+    fn reset_sequence(&self, trailing: Option<&Key>) -> bool {
+        self.key_sequence.lock().unwrap().clear();
+        if let Some(matcher) = self.matcher.lock().unwrap().as_mut() {
+            matcher.reset();
+        }
+        *self.pending.lock().unwrap() = None;
+        self.armed.lock().unwrap().clear();
+        *self.tap_dance.lock().unwrap() = None;
+        
+        let held = self.held.lock().unwrap().take();
+        match held {
+            Some(held) => {
+                self.replay_held(held, trailing);
+                trailing.is_some()
+            }
+            None => false,
+        }
+    }
+    
+    fn block_trigger_keys(&self, trigger: &ComboTrigger) {
+        // 更新屏蔽按键
+        if !trigger.block_keys.is_empty() {
+            debug!("设置屏蔽按键: {:?}", trigger.block_keys);
+            let mut blocked = self.blocked_keys.lock().unwrap();
+            for key in &trigger.block_keys {
+                blocked.insert(key.clone());
+            }
+        }
+    }
+    
+    fn update_key_sequence(&self, key: &Key, now: Instant) {
         let mut sequence = self.key_sequence.lock().unwrap();
         
         // 添加新按键到序列
@@ -192,22 +1148,420 @@ impl MacroEngine {
     }
     
     fn check_hero_switch_hotkey(&self, key: &Key) -> bool {
-        let hero_registry = self.hero_registry.lock().unwrap();
-        let hero_names = hero_registry.get_hero_names();
-        
-        for hero_name in &hero_names {
-            if let Some(hero_config) = hero_registry.get_hero(hero_name) {
-                if let Some(hotkey) = &hero_config.hotkey {
-                    if hotkey.len() == 1 && &hotkey[0] == key {
-                        let mut active = self.active_hero.lock().unwrap();
-                        *active = hero_name.clone();
-                        info!("切换到英雄: {}", hero_name);
-                        return true;
-                    }
-                }
+        let hero_name = {
+            let hero_registry = self.hero_registry.lock().unwrap();
+            hero_registry.get_hero_names().into_iter().find(|hero_name| {
+                hero_registry.get_hero(hero_name)
+                    .and_then(|hero_config| hero_config.hotkey.as_ref())
+                    .is_some_and(|hotkey| hotkey.len() == 1 && &hotkey[0] == key)
+            })
+        };
+        
+        match hero_name {
+            Some(hero_name) => self.switch_hero(&hero_name),
+            None => false,
+        }
+    }
+    
+    /// 切换当前英雄，英雄不存在时返回false
+    pub fn switch_hero(&self, hero_name: &str) -> bool {
+        if self.hero_registry.lock().unwrap().get_hero(hero_name).is_none() {
+            warn!("未找到英雄 [{}] 的配置，无法切换", hero_name);
+            return false;
+        }
+        
+        let mut active = self.active_hero.lock().unwrap();
+        *active = hero_name.to_string();
+        info!("切换到英雄: {}", hero_name);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::hero::{ActionTiming, HeroConfig, MouseButton};
+    use crate::config::settings::{LeaderCommand, LeaderSettings};
+    
+    /// 按顺序记下收到的动作，代替模拟器
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+    
+    impl Recorder {
+        fn actions(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+        
+        /// 连招动作在执行线程上异步执行，等到收到count个动作为止
+        fn wait_for(&self, count: usize) -> Vec<String> {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while self.0.lock().unwrap().len() < count && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(5));
             }
+            self.actions()
         }
         
-        false
+        /// 给执行线程留出时间，用来确认没有动作
+        fn settled(&self) -> Vec<String> {
+            thread::sleep(Duration::from_millis(50));
+            self.actions()
+        }
+    }
+    
+    impl ActionOutput for Recorder {
+        fn execute_actions_until(&mut self, actions: &[KeyAction], _timing: ActionTiming, _cancelled: &dyn Fn() -> bool) -> bool {
+            self.0.lock().unwrap().extend(actions.iter().map(|action| format!("{:?}", action)));
+            true
+        }
+        
+        fn release_held_keys(&mut self) {}
+    }
+    
+    struct Harness {
+        engine: MacroEngine,
+        /// 执行线程上执行的连招动作
+        combos: Recorder,
+        /// 引擎代替用户补发的按键
+        injected: Recorder,
+    }
+    
+    impl Harness {
+        fn new(config: HeroConfig) -> Self {
+            Self::with_settings(config, EngineSettings::default())
+        }
+        
+        fn with_settings(config: HeroConfig, settings: EngineSettings) -> Self {
+            let mut registry = HeroRegistry::new();
+            registry.register_hero("test", config);
+            let combos = Recorder::default();
+            let injected = Recorder::default();
+            let engine = MacroEngine::with_outputs(
+                Arc::new(Mutex::new(registry)),
+                Arc::new(Mutex::new("test".to_string())),
+                settings,
+                Arc::new(Mutex::new(KeyEventProcessor::new())),
+                Box::new(combos.clone()),
+                Box::new(injected.clone()),
+            );
+            Self { engine, combos, injected }
+        }
+        
+        fn press(&self, key: Key) -> bool {
+            self.engine.process_key_event(key, true)
+        }
+        
+        fn release(&self, key: Key) -> bool {
+            self.engine.process_key_event(key, false)
+        }
+        
+        /// 让后台线程的截止时间处理提前到after之后
+        fn advance(&self, after: Duration) {
+            self.engine.on_deadline(Instant::now() + after);
+        }
+    }
+    
+    fn key(c: char) -> Key {
+        Key::Character(c)
+    }
+    
+    fn down(key: Key) -> String {
+        format!("{:?}", KeyAction::Down(key))
+    }
+    
+    fn up(key: Key) -> String {
+        format!("{:?}", KeyAction::Up(key))
+    }
+    
+    fn press(key: Key) -> String {
+        format!("{:?}", KeyAction::Press(key))
+    }
+    
+    /// 添加一个严格按顺序的连招，触发时按下fires
+    fn strict<'a>(config: &'a mut HeroConfig, name: &str, keys: &str, fires: char) -> &'a mut ComboTrigger {
+        let sequence = keys.chars().map(key).collect();
+        let trigger = config.add_combo(name, sequence, vec![KeyAction::Press(key(fires))], Some(500), true, HashSet::new());
+        trigger.order = SequenceOrder::strict();
+        trigger
+    }
+    
+    /// E之后扣下按键最多200ms等Q
+    fn held_eq() -> HeroConfig {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "eq", "eq", 'x').hold_timeout = Some(200);
+        config
+    }
+    
+    #[test]
+    fn completed_combo_drops_held_key() {
+        let harness = Harness::new(held_eq());
+        
+        assert!(harness.press(key('e')));
+        assert!(harness.press(key('q')));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('x'))]);
+        
+        // 游戏没有收到E的按下，松开同样屏蔽
+        assert!(harness.release(key('e')));
+        assert!(harness.release(key('q')));
+        assert!(harness.injected.actions().is_empty());
+        assert_eq!(harness.combos.settled(), vec![press(key('x'))]);
+    }
+    
+    #[test]
+    fn timeout_replays_held_key_once() {
+        let harness = Harness::new(held_eq());
+        
+        assert!(harness.press(key('e')));
+        harness.advance(Duration::from_millis(300));
+        assert_eq!(harness.injected.actions(), vec![down(key('e'))]);
+        
+        harness.advance(Duration::from_millis(600));
+        assert_eq!(harness.injected.actions(), vec![down(key('e'))]);
+        
+        // 补发之后的松开照常放行
+        assert!(!harness.release(key('e')));
+        assert!(harness.combos.settled().is_empty());
+    }
+    
+    #[test]
+    fn other_key_replays_held_key_before_it() {
+        let harness = Harness::new(held_eq());
+        
+        assert!(harness.press(key('e')));
+        assert!(harness.release(key('e')));
+        assert!(harness.press(key('w')));
+        assert_eq!(harness.injected.actions(), vec![down(key('e')), up(key('e')), down(key('w'))]);
+        assert!(!harness.release(key('w')));
+    }
+    
+    #[test]
+    fn ignored_key_passes_through_while_held() {
+        let mut config = HeroConfig::new("test");
+        let trigger = strict(&mut config, "er", "er", 'x');
+        trigger.hold_timeout = Some(200);
+        trigger.ignored_keys = vec![Key::Mouse(MouseButton::Right)];
+        let harness = Harness::new(config);
+        
+        assert!(harness.press(key('e')));
+        // 移动用的右键照常发给游戏，不跟着E一起暂扣
+        assert!(!harness.press(Key::Mouse(MouseButton::Right)));
+        assert!(!harness.release(Key::Mouse(MouseButton::Right)));
+        
+        assert!(harness.press(key('r')));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('x'))]);
+        assert!(harness.release(key('e')));
+        assert!(harness.injected.actions().is_empty());
+    }
+    
+    fn single(kind: TriggerKind) -> HeroConfig {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "q", "q", 'x').kind = kind;
+        config
+    }
+    
+    #[test]
+    fn tap_fires_on_quick_release() {
+        let harness = Harness::new(single(TriggerKind::Tap { max_ms: 500 }));
+        
+        assert!(harness.press(key('q')));
+        assert!(harness.combos.settled().is_empty());
+        harness.release(key('q'));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('x'))]);
+    }
+    
+    #[test]
+    fn tap_held_too_long_does_not_fire() {
+        let harness = Harness::new(single(TriggerKind::Tap { max_ms: 10 }));
+        
+        harness.press(key('q'));
+        thread::sleep(Duration::from_millis(30));
+        harness.release(key('q'));
+        assert!(harness.combos.settled().is_empty());
+    }
+    
+    #[test]
+    fn long_press_fires_once_held_long_enough() {
+        let harness = Harness::new(single(TriggerKind::LongPress { min_ms: 100 }));
+        
+        assert!(harness.press(key('q')));
+        harness.advance(Duration::from_millis(50));
+        assert!(harness.combos.settled().is_empty());
+        
+        harness.advance(Duration::from_millis(150));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('x'))]);
+        
+        // 已经触发过，松开不再触发
+        harness.release(key('q'));
+        assert_eq!(harness.combos.settled(), vec![press(key('x'))]);
+    }
+    
+    #[test]
+    fn long_press_released_early_does_not_fire() {
+        let harness = Harness::new(single(TriggerKind::LongPress { min_ms: 100 }));
+        
+        harness.press(key('q'));
+        harness.release(key('q'));
+        harness.advance(Duration::from_millis(200));
+        assert!(harness.combos.settled().is_empty());
+    }
+    
+    /// Q连按一次按A，连按两次按B
+    fn tap_dances() -> HeroConfig {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "single", "q", 'a').kind = TriggerKind::TapDance { taps: 1, window_ms: 200 };
+        strict(&mut config, "double", "q", 'b').kind = TriggerKind::TapDance { taps: 2, window_ms: 200 };
+        config
+    }
+    
+    fn tap(harness: &Harness, key: Key) {
+        harness.press(key.clone());
+        harness.release(key);
+    }
+    
+    #[test]
+    fn tap_dance_waits_for_window_before_single_tap() {
+        let harness = Harness::new(tap_dances());
+        
+        tap(&harness, key('q'));
+        assert!(harness.combos.settled().is_empty());
+        harness.advance(Duration::from_millis(300));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('a'))]);
+    }
+    
+    #[test]
+    fn tap_dance_fires_at_max_taps_without_waiting() {
+        let harness = Harness::new(tap_dances());
+        
+        tap(&harness, key('q'));
+        tap(&harness, key('q'));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('b'))]);
+        
+        harness.advance(Duration::from_millis(300));
+        assert_eq!(harness.combos.settled(), vec![press(key('b'))]);
+    }
+    
+    #[test]
+    fn tap_dance_finishes_when_other_key_pressed() {
+        let harness = Harness::new(tap_dances());
+        
+        tap(&harness, key('q'));
+        harness.press(key('w'));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('a'))]);
+    }
+    
+    #[test]
+    fn tap_dance_without_matching_count_fires_nothing() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "double", "q", 'b').kind = TriggerKind::TapDance { taps: 2, window_ms: 200 };
+        let harness = Harness::new(config);
+        
+        tap(&harness, key('q'));
+        harness.advance(Duration::from_millis(300));
+        assert!(harness.combos.settled().is_empty());
+    }
+    
+    #[test]
+    fn dual_role_modifier_counts_as_held() {
+        let mut config = HeroConfig::new("test");
+        config.dual_role_keys.push(DualRoleKey {
+            key: Key::Space,
+            hold: HoldRole::Modifier(Key::LControl),
+            hold_after: 100,
+        });
+        strict(&mut config, "ctrl-q", "q", 'x').modifiers.required = vec![Key::Control];
+        let harness = Harness::new(config);
+        
+        // 没有按住修饰键时不触发
+        harness.press(key('q'));
+        harness.release(key('q'));
+        assert!(harness.combos.settled().is_empty());
+        
+        assert!(harness.press(Key::Space));
+        harness.advance(Duration::from_millis(200));
+        assert_eq!(harness.injected.actions(), vec![down(Key::LControl)]);
+        
+        assert!(harness.press(key('q')));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('x'))]);
+        harness.release(key('q'));
+        
+        // 双功能键松开后修饰键也一起松开
+        assert!(harness.release(Key::Space));
+        assert_eq!(harness.injected.actions(), vec![down(Key::LControl), up(Key::LControl)]);
+        harness.press(key('q'));
+        assert_eq!(harness.combos.settled(), vec![press(key('x'))]);
+    }
+    
+    #[test]
+    fn chord_requires_all_keys_still_held() {
+        let mut config = HeroConfig::new("test");
+        strict(&mut config, "qw", "qw", 'x').order = SequenceOrder::Chord { tolerance_ms: 200, suppress: false };
+        let harness = Harness::new(config);
+        
+        // Q在W按下之前已经松开，不算和弦
+        harness.press(key('q'));
+        harness.release(key('q'));
+        harness.press(key('w'));
+        harness.release(key('w'));
+        assert!(harness.combos.settled().is_empty());
+        
+        harness.press(key('q'));
+        harness.press(key('w'));
+        assert_eq!(harness.combos.wait_for(1), vec![press(key('x'))]);
+    }
+    
+    /// F12之后按P暂停，按S、C取消连招
+    fn leader_settings() -> EngineSettings {
+        EngineSettings {
+            leader: Some(LeaderSettings {
+                key: Key::F12,
+                timeout: 500,
+                commands: vec![
+                    LeaderCommand { sequence: vec![key('p')], action: LeaderAction::TogglePause },
+                    LeaderCommand { sequence: vec![key('s'), key('c')], action: LeaderAction::CancelCombos },
+                ],
+            }),
+            ..EngineSettings::default()
+        }
+    }
+    
+    #[test]
+    fn leader_command_toggles_pause() {
+        let harness = Harness::with_settings(held_eq(), leader_settings());
+        
+        assert!(harness.press(Key::F12));
+        assert!(harness.press(key('p')));
+        assert!(harness.engine.is_paused());
+        
+        // 命令按键的松开同样不发给游戏，暂停后连招按键原样放行
+        assert!(harness.release(Key::F12));
+        assert!(harness.release(key('p')));
+        assert!(!harness.press(key('e')));
+        
+        harness.press(Key::F12);
+        harness.press(key('p'));
+        assert!(!harness.engine.is_paused());
+    }
+    
+    #[test]
+    fn leader_abandons_unknown_sequence() {
+        let harness = Harness::with_settings(held_eq(), leader_settings());
+        
+        assert!(harness.press(Key::F12));
+        assert!(harness.press(key('s')));
+        assert!(harness.press(key('p')));
+        assert!(!harness.engine.is_paused());
+        
+        // 前导序列已经结束，P照常发给游戏
+        harness.release(key('p'));
+        assert!(!harness.press(key('p')));
+    }
+    
+    #[test]
+    fn leader_times_out() {
+        let harness = Harness::with_settings(held_eq(), leader_settings());
+        
+        assert!(harness.press(Key::F12));
+        harness.advance(Duration::from_millis(600));
+        assert!(!harness.press(key('p')));
+        assert!(!harness.engine.is_paused());
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file
@@ -1,31 +1,49 @@
+use std::sync::{Condvar, Mutex};
 use std::time::{Duration, Instant};
 
-pub struct TimingWindow {
-    start_time: Instant,
-    duration: Duration,
+/// 后台线程等待的最近截止时间
+pub struct Deadline {
+    next: Mutex<Option<Instant>>,
+    changed: Condvar,
 }
 
-impl TimingWindow {
-    pub fn new(duration_ms: u64) -> Self {
+impl Deadline {
+    pub fn new() -> Self {
         Self {
-            start_time: Instant::now(),
-            duration: Duration::from_millis(duration_ms),
+            next: Mutex::new(None),
+            changed: Condvar::new(),
         }
     }
     
-    pub fn reset(&mut self) {
-        self.start_time = Instant::now();
-    }
-    
-    pub fn is_expired(&self) -> bool {
-        Instant::now().duration_since(self.start_time) > self.duration
+    /// 登记一个截止时间，只保留最早的一个
+    pub fn schedule(&self, at: Instant) {
+        let mut next = self.next.lock().unwrap();
+        if next.map_or(true, |current| at < current) {
+            *next = Some(at);
+            self.changed.notify_all();
+        }
     }
     
-    pub fn remaining_ms(&self) -> u64 {
-        let elapsed = Instant::now().duration_since(self.start_time);
-        if elapsed > self.duration {
-            return 0;
+    /// 等待直到截止时间到达，最多等待max_wait；截止时间到达时返回true并清除它
+    pub fn wait(&self, max_wait: Duration) -> bool {
+        let mut next = self.next.lock().unwrap();
+        let give_up = Instant::now() + max_wait;
+        
+        loop {
+            let now = Instant::now();
+            match *next {
+                Some(at) if at <= now => {
+                    *next = None;
+                    return true;
+                }
+                _ if now >= give_up => return false,
+                Some(at) => {
+                    next = self.changed.wait_timeout(next, at.min(give_up) - now).unwrap().0;
+                }
+                None => {
+                    next = self.changed.wait_timeout(next, give_up - now).unwrap().0;
+                }
+            }
         }
-        (self.duration - elapsed).as_millis() as u64
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file